@@ -1,20 +1,153 @@
-use crate::types::{AggregationMethod, SourcePrice};
+use crate::decimal::Decimal;
+use crate::types::{AggregationMethod, DataValue, SourcePrice};
 use std::error::Error;
 
+// Decimal places kept in deterministic mode
+pub const DETERMINISTIC_DECIMALS: i32 = 8;
+
 /// Calculate aggregated price from multiple source prices
+/// In deterministic mode inputs are sorted (by value, then source name) so every replica
+/// sums in the same order, and the result is rounded to DETERMINISTIC_DECIMALS
+/// With `use_decimal` the arithmetic is fixed-point instead (see aggregate_decimal)
 pub fn aggregate_prices(
     prices: &[SourcePrice],
     method: &AggregationMethod,
+    deterministic: bool,
+    use_decimal: bool,
 ) -> Result<f64, Box<dyn Error>> {
     if prices.is_empty() {
         return Err("No prices to aggregate".into());
     }
 
+    if use_decimal {
+        return aggregate_decimal(prices, method, deterministic);
+    }
+
+    // Latest picks by timestamp and request order; there is no summation to reorder
+    if deterministic && !matches!(method, AggregationMethod::Latest) {
+        let mut sorted = prices.to_vec();
+        sorted.sort_by(|a, b| {
+            let a_val = a.value.as_number().unwrap_or(f64::NAN);
+            let b_val = b.value.as_number().unwrap_or(f64::NAN);
+            a_val.total_cmp(&b_val).then_with(|| a.source_name.cmp(&b.source_name))
+        });
+
+        return Ok(round_deterministic(aggregate_by_method(&sorted, method)?));
+    }
+
+    aggregate_by_method(prices, method)
+}
+
+/// Round to DETERMINISTIC_DECIMALS so replicas agree bit-for-bit
+pub fn round_deterministic(value: f64) -> f64 {
+    let scale = 10f64.powi(DETERMINISTIC_DECIMALS);
+    (value * scale).round() / scale
+}
+
+/// Whether `use_decimal` can aggregate with this method (the weighted ones, weighted_avg
+/// included, need float weights)
+pub fn supports_decimal(method: &AggregationMethod) -> bool {
+    matches!(method, AggregationMethod::Average | AggregationMethod::Median | AggregationMethod::MaxMinusMin)
+}
+
+/// Fixed-point aggregation: each value is taken as the decimal the source sent, sums are exact,
+/// divisions round half to even at 18 places, and deterministic rounding is decimal too
+/// Converted back to the nearest f64 only at the end; values beyond about ±1.7e20 fail
+fn aggregate_decimal(
+    prices: &[SourcePrice],
+    method: &AggregationMethod,
+    deterministic: bool,
+) -> Result<f64, Box<dyn Error>> {
+    let mut values: Vec<Decimal> = prices.iter()
+        .filter_map(|p| p.value.as_number())
+        .map(Decimal::from_f64)
+        .collect::<Result<_, _>>()?;
+
+    if values.is_empty() {
+        return Err("No numeric values to aggregate".into());
+    }
+    // Exact sums don't depend on order; sorting is for the median and the spread
+    values.sort();
+
+    let len = values.len();
+    let aggregate = match method {
+        AggregationMethod::Average => {
+            let sum = values.iter().try_fold(Decimal::ZERO, |sum, v| sum.checked_add(*v))?;
+            sum.div_int(len as i128)
+        }
+        AggregationMethod::Median if len % 2 == 0 => {
+            values[len / 2 - 1].checked_add(values[len / 2])?.div_int(2)
+        }
+        AggregationMethod::Median => values[len / 2],
+        AggregationMethod::MaxMinusMin => values[len - 1].checked_sub(values[0])?,
+        _ => return Err(format!("use_decimal does not support {:?}", method).into()),
+    };
+
+    let aggregate = if deterministic { aggregate.round_dp(DETERMINISTIC_DECIMALS as u32) } else { aggregate };
+    Ok(aggregate.to_f64())
+}
+
+/// Dispatch to the selected aggregation method
+fn aggregate_by_method(prices: &[SourcePrice], method: &AggregationMethod) -> Result<f64, Box<dyn Error>> {
     match method {
         AggregationMethod::Average => calculate_average(prices),
         AggregationMethod::Median => calculate_median(prices),
+        AggregationMethod::Latest => latest_source(prices)
+            .and_then(|p| p.value.as_number())
+            .ok_or_else(|| "No numeric values to aggregate".into()),
         AggregationMethod::WeightedAvg => calculate_weighted_average(prices),
+        AggregationMethod::FreshnessWeighted => calculate_freshness_weighted(prices),
+        AggregationMethod::ReputationWeighted => calculate_reputation_weighted(prices),
+        AggregationMethod::InverseVarianceWeighted => calculate_inverse_variance_weighted(prices),
+        AggregationMethod::MaxMinusMin => calculate_max_minus_min(prices),
+        AggregationMethod::RobustIrls => weighted_mean(prices, &robust_irls_weights(prices)),
+        AggregationMethod::BoolAnd | AggregationMethod::BoolOr | AggregationMethod::BoolMajority => {
+            Err("Boolean aggregation methods require boolean values".into())
+        }
+    }
+}
+
+/// Boolean vote result
+pub struct BoolVote {
+    pub value: bool,
+    pub true_votes: usize,
+    pub false_votes: usize,
+}
+
+/// Aggregate boolean sources with AND / OR / majority
+/// All values must be booleans; numbers and text are rejected rather than coerced
+pub fn aggregate_booleans(
+    prices: &[SourcePrice],
+    method: &AggregationMethod,
+) -> Result<BoolVote, Box<dyn Error>> {
+    let mut true_votes = 0;
+    let mut false_votes = 0;
+
+    for price in prices {
+        match price.value {
+            DataValue::Boolean(true) => true_votes += 1,
+            DataValue::Boolean(false) => false_votes += 1,
+            _ => return Err(format!("{} returned a non-boolean value", price.source_name).into()),
+        }
+    }
+
+    if true_votes + false_votes == 0 {
+        return Err("No boolean values to aggregate".into());
     }
+
+    let value = match method {
+        AggregationMethod::BoolAnd => false_votes == 0,
+        AggregationMethod::BoolOr => true_votes > 0,
+        AggregationMethod::BoolMajority => {
+            if true_votes == false_votes {
+                return Err(format!("No majority ({} true / {} false)", true_votes, false_votes).into());
+            }
+            true_votes > false_votes
+        }
+        _ => return Err("Not a boolean aggregation method".into()),
+    };
+
+    Ok(BoolVote { value, true_votes, false_votes })
 }
 
 /// Calculate arithmetic mean
@@ -31,37 +164,366 @@ fn calculate_average(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
     Ok(sum / numbers.len() as f64)
 }
 
-/// Calculate median (middle value when sorted)
-fn calculate_median(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
-    let mut sorted_prices: Vec<f64> = prices.iter()
+/// Numeric values sorted ascending (total order, so a NaN can't panic the sort)
+fn sorted_numbers(prices: &[SourcePrice]) -> Vec<f64> {
+    let mut numbers: Vec<f64> = prices.iter()
         .filter_map(|p| p.value.as_number())
         .collect();
+    numbers.sort_by(|a, b| a.total_cmp(b));
+    numbers
+}
 
-    if sorted_prices.is_empty() {
+/// Calculate median (middle value when sorted)
+fn calculate_median(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
+    let sorted = sorted_numbers(prices);
+    if sorted.is_empty() {
         return Err("No numeric values to aggregate".into());
     }
 
-    sorted_prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    let len = sorted_prices.len();
+    let len = sorted.len();
     if len % 2 == 0 {
         // Even number of prices: average of two middle values
-        Ok((sorted_prices[len / 2 - 1] + sorted_prices[len / 2]) / 2.0)
+        Ok((sorted[len / 2 - 1] + sorted[len / 2]) / 2.0)
     } else {
         // Odd number of prices: middle value
-        Ok(sorted_prices[len / 2])
+        Ok(sorted[len / 2])
     }
 }
 
-/// Calculate weighted average (currently using equal weights)
+/// The two middle values (low, high) of an even number of numeric values; None for an odd count
+pub fn median_middle_pair(prices: &[SourcePrice]) -> Option<(f64, f64)> {
+    let sorted = sorted_numbers(prices);
+    let len = sorted.len();
+    if len == 0 || len % 2 != 0 {
+        return None;
+    }
+
+    Some((sorted[len / 2 - 1], sorted[len / 2]))
+}
+
+/// Source whose value is the median of an odd number of numeric values; None for an even count
+/// When several sources share the median value, the first of them in request order is named
+pub fn median_source(prices: &[SourcePrice]) -> Option<&str> {
+    let sorted = sorted_numbers(prices);
+    let len = sorted.len();
+    if len % 2 == 0 {
+        return None;
+    }
+
+    // The middle slot can hold any of several equal values, so name the first in request order
+    let median = sorted[len / 2];
+    prices.iter()
+        .find(|p| p.value.as_number().is_some_and(|n| n.total_cmp(&median).is_eq()))
+        .map(|p| p.source_name.as_str())
+}
+
+/// Numeric source with the newest timestamp; ties go to the first in request order
+pub fn latest_source(prices: &[SourcePrice]) -> Option<&SourcePrice> {
+    prices.iter()
+        .filter(|p| p.value.as_number().is_some())
+        .fold(None, |latest: Option<&SourcePrice>, p| match latest {
+            Some(l) if l.timestamp >= p.timestamp => Some(l),
+            _ => Some(p),
+        })
+}
+
+/// Source whose value is the aggregate, when the method picks one value rather than blending:
+/// an odd-count median, latest, or a text result (the first source's value)
+pub fn selected_source<'a>(prices: &'a [SourcePrice], method: &AggregationMethod, value: &DataValue) -> Option<&'a str> {
+    match (value, method) {
+        (DataValue::Number(_), AggregationMethod::Median) => median_source(prices),
+        (DataValue::Number(_), AggregationMethod::Latest) => latest_source(prices).map(|p| p.source_name.as_str()),
+        (DataValue::Text(_), _) => prices.first().map(|p| p.source_name.as_str()),
+        _ => None,
+    }
+}
+
+/// Calculate spread between the largest and smallest value (e.g. block height divergence)
+fn calculate_max_minus_min(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
+    let numbers: Vec<f64> = prices.iter()
+        .filter_map(|p| p.value.as_number())
+        .collect();
+
+    if numbers.is_empty() {
+        return Err("No numeric values to aggregate".into());
+    }
+
+    let max = numbers.iter().cloned().fold(f64::MIN, f64::max);
+    let min = numbers.iter().cloned().fold(f64::MAX, f64::min);
+    Ok(max - min)
+}
+
+/// Calculate weighted average (equal weights, so only staleness tiers weigh in)
 fn calculate_weighted_average(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
-    // For now, use equal weights (same as average)
-    // Can be extended with reputation-based weighting
-    calculate_average(prices)
+    if prices.iter().all(|p| p.tier_weight.is_none()) {
+        return calculate_average(prices);
+    }
+    weighted_mean(prices, &vec![1.0; prices.len()])
+}
+
+/// Freshness weight per source: 1 / (age + 1), where age is seconds behind the freshest source
+/// Measured against the newest timestamp rather than the wall clock so results are reproducible
+pub fn freshness_weights(prices: &[SourcePrice]) -> Vec<f64> {
+    let newest = prices.iter().map(|p| p.timestamp).max().unwrap_or(0);
+
+    prices.iter()
+        .map(|p| 1.0 / ((newest - p.timestamp) as f64 + 1.0))
+        .collect()
+}
+
+/// Calculate average weighted by source freshness
+fn calculate_freshness_weighted(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
+    weighted_mean(prices, &freshness_weights(prices))
+}
+
+/// Default reputation weight per source, used by `reputation_weighted` when no explicit `weight` is set
+/// Deep, well-arbitraged venues and reference aggregators rank high; thinner venues rank low
+pub const REPUTATION_WEIGHTS: &[(&str, f64)] = &[
+    ("binance", 1.0),
+    ("coinbase_rates", 1.0),
+    ("pyth", 0.9),
+    ("coingecko", 0.8),
+    ("coinmarketcap", 0.8),
+    ("deribit", 0.8),
+    ("kucoin", 0.6),
+    ("cryptocom", 0.6),
+    ("messari", 0.6),
+    ("twelvedata", 0.6),
+    ("exchangerate-api", 0.6),
+    ("huobi", 0.5),
+    ("bithumb", 0.5),
+    ("gate", 0.4),
+];
+
+/// Weight for sources missing from REPUTATION_WEIGHTS (including custom sources)
+pub const DEFAULT_REPUTATION_WEIGHT: f64 = 0.5;
+
+/// Reputation weight per source: explicit `weight` first, then the built-in table
+/// The table is keyed by the built-in source, so a `label` doesn't change the tier
+pub fn reputation_weights(prices: &[SourcePrice]) -> Vec<f64> {
+    prices.iter()
+        .map(|p| {
+            p.weight.unwrap_or_else(|| {
+                REPUTATION_WEIGHTS.iter()
+                    .find(|(name, _)| *name == p.provider)
+                    .map(|(_, weight)| *weight)
+                    .unwrap_or(DEFAULT_REPUTATION_WEIGHT)
+            })
+        })
+        .collect()
+}
+
+/// Calculate average weighted by source reputation
+fn calculate_reputation_weighted(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
+    weighted_mean(prices, &reputation_weights(prices))
+}
+
+// Reweighting passes for robust_irls (fixed so every replica computes the same weights)
+pub const IRLS_ITERATIONS: usize = 3;
+
+/// Iteratively reweighted weights: start equal, then each pass takes the weighted median and
+/// sets w = 1 / (1 + d / s), where d is the distance to it and s the median distance (MAD)
+/// Outliers fade out smoothly instead of being cut at a hard threshold; non-numeric sources get 0
+pub fn robust_irls_weights(prices: &[SourcePrice]) -> Vec<f64> {
+    let values: Vec<Option<f64>> = prices.iter().map(|p| p.value.as_number()).collect();
+    let mut weights: Vec<f64> = values.iter().map(|v| if v.is_some() { 1.0 } else { 0.0 }).collect();
+
+    for _ in 0..IRLS_ITERATIONS {
+        let center = match weighted_median(&values, &weights) {
+            Some(center) => center,
+            None => break,
+        };
+
+        let distances: Vec<Option<f64>> = values.iter().map(|v| v.map(|n| (n - center).abs())).collect();
+        let mut sorted: Vec<f64> = distances.iter().flatten().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        // All-but-outliers identical gives MAD 0; fall back to a relative scale so weights stay finite
+        let scale = match sorted.get(sorted.len() / 2) {
+            Some(&mad) if mad > 0.0 => mad,
+            _ => center.abs().max(1.0) * 1e-9,
+        };
+
+        for (weight, distance) in weights.iter_mut().zip(&distances) {
+            if let Some(d) = distance {
+                *weight = 1.0 / (1.0 + d / scale);
+            }
+        }
+    }
+
+    weights
+}
+
+/// Value where the cumulative weight (in value order) first reaches half the total
+fn weighted_median(values: &[Option<f64>], weights: &[f64]) -> Option<f64> {
+    let mut pairs: Vec<(f64, f64)> = values.iter()
+        .zip(weights)
+        .filter_map(|(v, w)| v.map(|n| (n, *w)))
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let total: f64 = pairs.iter().map(|(_, w)| w).sum();
+    let mut cumulative = 0.0;
+    for (value, weight) in &pairs {
+        cumulative += weight;
+        if cumulative >= total / 2.0 {
+            return Some(*value);
+        }
+    }
+
+    pairs.last().map(|(value, _)| *value)
+}
+
+/// Weighted mean of numeric values (weights are aligned with `prices`, scaled by staleness tier)
+fn weighted_mean(prices: &[SourcePrice], weights: &[f64]) -> Result<f64, Box<dyn Error>> {
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0.0;
+    for (price, weight) in prices.iter().zip(weights) {
+        let weight = weight * price.tier_weight.unwrap_or(1.0);
+        if let Some(n) = price.value.as_number() {
+            weighted_sum += n * weight;
+            total_weight += weight;
+        }
+    }
+
+    if total_weight == 0.0 {
+        return Err("No numeric values with a non-zero weight to aggregate".into());
+    }
+
+    Ok(weighted_sum / total_weight)
+}
+
+/// Find the largest group of numeric sources whose spread is within `agreement_percent`
+/// Returns indices into `prices`; on ties the lower-valued group wins
+pub fn find_agreement_cluster(prices: &[SourcePrice], agreement_percent: f64) -> Vec<usize> {
+    let mut numbers: Vec<(usize, f64)> = prices.iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.value.as_number().map(|n| (i, n)))
+        .collect();
+
+    numbers.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    // Sliding window over sorted values: widest window whose (max - min) / min stays in range
+    let mut best = (0, 0);
+    let mut start = 0;
+    for end in 0..numbers.len() {
+        while start < end && spread_percent(numbers[start].1, numbers[end].1) > agreement_percent {
+            start += 1;
+        }
+        if end + 1 - start > best.1 - best.0 {
+            best = (start, end + 1);
+        }
+    }
+
+    numbers[best.0..best.1].iter().map(|(i, _)| *i).collect()
+}
+
+/// Spread between two values as a percentage of the lower one
+fn spread_percent(low: f64, high: f64) -> f64 {
+    if low == 0.0 {
+        return if high == 0.0 { 0.0 } else { 100.0 };
+    }
+
+    ((high - low) / low) * 100.0
+}
+
+/// Price band width: (max - min) / median * 100 over numeric sources
+/// None with fewer than two numeric values or a zero median
+pub fn spread_over_median(prices: &[SourcePrice]) -> Option<f64> {
+    let numbers: Vec<f64> = prices.iter()
+        .filter_map(|p| p.value.as_number())
+        .collect();
+
+    if numbers.len() < 2 {
+        return None;
+    }
+
+    let median = calculate_median(prices).ok()?;
+    if median == 0.0 {
+        return None;
+    }
+
+    let max = numbers.iter().cloned().fold(f64::MIN, f64::max);
+    let min = numbers.iter().cloned().fold(f64::MAX, f64::min);
+    Some((max - min) / median.abs() * 100.0)
+}
+
+/// Index of the numeric source farthest from the median (None if no numeric values)
+pub fn farthest_from_median(prices: &[SourcePrice]) -> Option<usize> {
+    let median = calculate_median(prices).ok()?;
+
+    prices.iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.value.as_number().map(|n| (i, (n - median).abs())))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+// Confidence assumed for sources that don't report one, as a percentage of their value
+pub const DEFAULT_CONFIDENCE_PERCENT: f64 = 1.0;
+
+/// Confidence used for weighting: the reported one, or DEFAULT_CONFIDENCE_PERCENT of the value
+fn effective_confidence(price: &SourcePrice) -> Option<f64> {
+    let value = price.value.as_number()?;
+
+    match price.confidence {
+        Some(c) if c > 0.0 => Some(c),
+        _ => {
+            let fallback = value.abs() * DEFAULT_CONFIDENCE_PERCENT / 100.0;
+            if fallback > 0.0 { Some(fallback) } else { None }
+        }
+    }
+}
+
+/// Calculate inverse-variance weighted average: weight = 1 / conf^2
+/// The statistically optimal combination of independent estimates
+fn calculate_inverse_variance_weighted(prices: &[SourcePrice]) -> Result<f64, Box<dyn Error>> {
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0.0;
+
+    for price in prices {
+        if let (Some(value), Some(conf)) = (price.value.as_number(), effective_confidence(price)) {
+            let weight = price.tier_weight.unwrap_or(1.0) / (conf * conf);
+            weighted_sum += value * weight;
+            total_weight += weight;
+        }
+    }
+
+    if total_weight == 0.0 {
+        return Err("No numeric values with usable confidence to aggregate".into());
+    }
+
+    Ok(weighted_sum / total_weight)
+}
+
+/// Combined confidence of the inverse-variance weighted estimate: 1 / sqrt(sum(1 / conf^2))
+pub fn combined_confidence(prices: &[SourcePrice]) -> Option<f64> {
+    let total_weight: f64 = prices.iter()
+        .filter_map(effective_confidence)
+        .map(|c| 1.0 / (c * c))
+        .sum();
+
+    if total_weight > 0.0 {
+        Some(1.0 / total_weight.sqrt())
+    } else {
+        None
+    }
+}
+
+/// Distance of a source value from the aggregate, as a percentage of the aggregate
+/// A zero aggregate counts any non-zero value as 100% off (as in the spread check)
+pub fn deviation_from(value: f64, aggregate: f64) -> f64 {
+    if aggregate == 0.0 {
+        return if value == 0.0 { 0.0 } else { 100.0 };
+    }
+
+    ((value - aggregate) / aggregate).abs() * 100.0
 }
 
 /// Calculate price deviation percentage between min and max prices
-pub fn calculate_price_deviation(prices: &[SourcePrice]) -> f64 {
+/// A spread within `epsilon` (relative to the larger magnitude, absolute below 1.0) counts as zero,
+/// so float noise like 100.0000001 vs 100.0 never fails the percentage check
+/// With `use_decimal` the spread (max - min) is the exact decimal difference
+pub fn calculate_price_deviation(prices: &[SourcePrice], epsilon: f64, use_decimal: bool) -> f64 {
     let numbers: Vec<f64> = prices.iter()
         .filter_map(|p| p.value.as_number())
         .collect();
@@ -82,9 +544,110 @@ pub fn calculate_price_deviation(prices: &[SourcePrice]) -> f64 {
         }
     }
 
+    let spread = match (use_decimal, Decimal::from_f64(max_price), Decimal::from_f64(min_price)) {
+        (true, Ok(max), Ok(min)) => max.checked_sub(min).map(Decimal::to_f64).unwrap_or(max_price - min_price),
+        _ => max_price - min_price,
+    };
+
+    let scale = min_price.abs().max(max_price.abs()).max(1.0);
+    if spread <= epsilon * scale {
+        return 0.0;
+    }
+
     if min_price == 0.0 {
         return 100.0;
     }
 
-    ((max_price - min_price) / min_price) * 100.0
+    (spread / min_price) * 100.0
+}
+
+/// Check whether all numeric values are bit-identical (requires at least 2 values)
+pub fn all_values_identical(prices: &[SourcePrice]) -> bool {
+    let numbers: Vec<f64> = prices.iter()
+        .filter_map(|p| p.value.as_number())
+        .collect();
+
+    if numbers.len() < 2 {
+        return false;
+    }
+
+    numbers.iter().all(|n| n.to_bits() == numbers[0].to_bits())
+}
+
+/// Count significant digits in the shortest decimal form of a value
+/// e.g. 100.0 -> 1, 100.1347 -> 7, 0.00052 -> 2
+pub fn significant_digits(value: f64) -> usize {
+    // Display prints the shortest round-trip form without an exponent
+    let digits: String = format!("{}", value.abs())
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect();
+
+    digits.trim_start_matches('0').trim_end_matches('0').len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(source: &str, value: f64, timestamp: u64) -> SourcePrice {
+        SourcePrice::new(source.to_string(), DataValue::Number(value), timestamp)
+    }
+
+    #[test]
+    fn reputation_weights_use_the_builtin_name() {
+        let mut labeled = number("binance", 100.0, 0);
+        labeled.source_name = "my_binance".to_string();
+        let mut explicit = number("gate", 100.0, 0);
+        explicit.weight = Some(0.7);
+        let prices = vec![labeled, number("coinbase_rates", 100.0, 0), explicit, number("custom", 100.0, 0)];
+
+        assert_eq!(reputation_weights(&prices), vec![1.0, 1.0, 0.7, DEFAULT_REPUTATION_WEIGHT]);
+    }
+
+    #[test]
+    fn selected_source_names_the_picked_value() {
+        let value = DataValue::Number(0.0);
+        let odd = vec![number("binance", 101.0, 10), number("kraken", 99.0, 10), number("okx", 100.0, 10)];
+        assert_eq!(selected_source(&odd, &AggregationMethod::Median, &value), Some("okx"));
+        assert_eq!(aggregate_prices(&odd, &AggregationMethod::Median, false, false).unwrap(), 100.0);
+
+        let even = vec![number("binance", 101.0, 10), number("kraken", 99.0, 10)];
+        assert_eq!(selected_source(&even, &AggregationMethod::Median, &value), None);
+        assert_eq!(selected_source(&odd, &AggregationMethod::Average, &value), None);
+
+        // Newest timestamp wins, ties go to the first in request order
+        let latest = vec![number("binance", 101.0, 10), number("kraken", 99.0, 12), number("okx", 100.0, 12)];
+        assert_eq!(selected_source(&latest, &AggregationMethod::Latest, &value), Some("kraken"));
+        assert_eq!(aggregate_prices(&latest, &AggregationMethod::Latest, false, false).unwrap(), 99.0);
+        assert_eq!(aggregate_prices(&latest, &AggregationMethod::Latest, true, false).unwrap(), 99.0);
+
+        let text = vec![
+            SourcePrice::new("exchange_a".to_string(), DataValue::Text("open".to_string()), 10),
+            SourcePrice::new("exchange_b".to_string(), DataValue::Text("open".to_string()), 12),
+        ];
+        let status = DataValue::Text("open".to_string());
+        assert_eq!(selected_source(&text, &AggregationMethod::Median, &status), Some("exchange_a"));
+        assert_eq!(selected_source(&text, &AggregationMethod::Average, &DataValue::Boolean(true)), None);
+    }
+
+    #[test]
+    fn nan_sources_do_not_panic_the_sorts() {
+        let prices = vec![number("binance", 100.0, 10), number("custom", f64::NAN, 10), number("okx", 101.0, 10)];
+        assert!(calculate_median(&prices).is_ok());
+        find_agreement_cluster(&prices, 5.0);
+        farthest_from_median(&prices);
+    }
+
+    #[test]
+    fn median_helpers_share_one_ordering() {
+        let even = vec![number("a", 103.0, 0), number("b", 100.0, 0), number("c", 110.0, 0), number("d", 101.0, 0)];
+        assert_eq!(median_middle_pair(&even), Some((101.0, 103.0)));
+        assert_eq!(calculate_median(&even).unwrap(), 102.0);
+
+        let tied = vec![number("a", 101.0, 0), number("b", 100.0, 0), number("c", 100.0, 0)];
+        assert_eq!(median_source(&tied), Some("b"));
+        assert_eq!(calculate_median(&tied).unwrap(), 100.0);
+        assert_eq!(median_middle_pair(&tied), None);
+    }
 }
@@ -0,0 +1,166 @@
+use crate::sources::near_view_call_key;
+use crate::types::{DataValue, PriceSource, SourcePrice};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+
+/// Short hex digest of a source's fetch settings; settings that only act after the fetch
+/// (weight, label, value_type, canary, unit_convert) and the id (already in the key) are left out
+/// Hashed rather than spelled out, since custom headers may carry secrets
+fn fetch_options_digest(source: &PriceSource) -> String {
+    let mut options = source.clone();
+    options.id = None;
+    options.weight = None;
+    options.label = None;
+    options.value_type = None;
+    options.canary = false;
+    options.unit_convert = None;
+
+    let canonical = serde_json::to_vec(&options).unwrap_or_default();
+    hex::encode(&Sha256::digest(&canonical)[..8])
+}
+
+/// A successful source value kept between invocations
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CachedPrice {
+    pub value: DataValue,
+    /// Timestamp reported with the value
+    pub timestamp: u64,
+    /// When the value was fetched (unix seconds); TTL is measured from here
+    pub fetched_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Allowed key that signed the value (verify_signature sources)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_by: Option<String>,
+}
+
+/// Per-source response cache (JSON file in a WASI preopened directory)
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SourceCache {
+    /// "source|id|options" -> last successful value
+    #[serde(default)]
+    pub entries: HashMap<String, CachedPrice>,
+}
+
+impl SourceCache {
+    /// Load cache from file (missing file = empty cache)
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(SourceCache::default()),
+            Err(e) => Err(format!("Failed to read cache file '{}': {}", path, e).into()),
+        }
+    }
+
+    /// Write cache back to file
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let bytes = serde_json::to_vec(self)?;
+        fs::write(path, bytes)
+            .map_err(|e| format!("Failed to write cache file '{}': {}", path, e))?;
+        Ok(())
+    }
+
+    /// Cache key for a source fetch: "source|id|options", where options is a digest of every setting
+    /// that changes what is fetched (quote, platform, candles, custom method/body/headers, ...)
+    /// Custom sources are keyed by URL instead of the request id, so requests sharing an endpoint share the entry
+    pub fn key(source_name: &str, source: &PriceSource, id: &str) -> String {
+        if let (Some(view), "near_view") = (&source.near_view, source_name) {
+            return format!("near_view|{}", near_view_call_key(view, id));
+        }
+
+        let subject = match &source.custom {
+            Some(custom) if source_name == "custom" => custom.url.as_str(),
+            _ => id,
+        };
+        format!("{}|{}|{}", source_name, subject, fetch_options_digest(source))
+    }
+
+    /// Cached value if it was fetched at most `ttl_secs` ago
+    pub fn get(&self, key: &str, now: u64, ttl_secs: u64) -> Option<SourcePrice> {
+        let cached = self.entries.get(key)?;
+        if now.saturating_sub(cached.fetched_at) > ttl_secs {
+            return None;
+        }
+
+        let source_name = key.split('|').next().unwrap_or(key).to_string();
+        let mut price = SourcePrice::new(source_name, cached.value.clone(), cached.timestamp);
+        price.confidence = cached.confidence;
+        price.currency = cached.currency.clone();
+        price.detail.name = price.source_name.clone();
+        price.detail.currency = price.currency.clone();
+        price.detail.signed_by = cached.signed_by.clone();
+        price.detail.cached = true;
+        Some(price)
+    }
+
+    /// Remember a freshly fetched value
+    pub fn insert(&mut self, key: String, price: &SourcePrice, now: u64) {
+        self.entries.insert(key, CachedPrice {
+            value: price.value.clone(),
+            timestamp: price.timestamp,
+            fetched_at: now,
+            confidence: price.confidence,
+            currency: price.currency.clone(),
+            signed_by: price.detail.signed_by.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn source(value: serde_json::Value) -> PriceSource {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn key_covers_every_fetch_setting() {
+        let key = |value: serde_json::Value| {
+            let source = source(value);
+            SourceCache::key(&source.name, &source, "bitcoin")
+        };
+
+        let usd = key(json!({"name": "coingecko"}));
+        assert_ne!(usd, key(json!({"name": "coingecko", "quote": "eur"})));
+        assert_ne!(usd, key(json!({"name": "coingecko", "platform": "ethereum"})));
+        assert_ne!(key(json!({"name": "binance"})), key(json!({"name": "binance", "candles": 5})));
+
+        // Settings applied after the fetch share the entry (the cache keeps the native unit)
+        assert_eq!(usd, key(json!({"name": "coingecko", "label": "cg", "weight": 0.9})));
+        assert_eq!(usd, key(json!({"name": "coingecko", "unit_convert": 0.5})));
+        assert!(usd.starts_with("coingecko|bitcoin|"));
+    }
+
+    #[test]
+    fn custom_key_covers_body_graphql_and_headers() {
+        let custom = |custom: serde_json::Value| {
+            let source = source(json!({"name": "custom", "custom": custom}));
+            SourceCache::key("custom", &source, "ignored")
+        };
+
+        let base = json!({"url": "https://example.com/api", "method": "POST", "json_path": "price", "body": {"pair": "BTC"}});
+        let plain = custom(base.clone());
+        assert!(plain.starts_with("custom|https://example.com/api|"));
+
+        let mut other_body = base.clone();
+        other_body["body"] = json!({"pair": "ETH"});
+        assert_ne!(plain, custom(other_body));
+
+        let mut with_header = base.clone();
+        with_header["headers"] = json!([["X-Api-Key", "secret"]]);
+        let header_key = custom(with_header);
+        assert_ne!(plain, header_key);
+        assert!(!header_key.contains("secret"));
+
+        let graphql = |query: &str| custom(json!({"url": "https://example.com/graphql", "json_path": "pool.price", "graphql": {"query": query}}));
+        assert_ne!(graphql("{ pool(id: \"a\") { price } }"), graphql("{ pool(id: \"b\") { price } }"));
+    }
+}
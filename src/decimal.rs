@@ -0,0 +1,120 @@
+use std::fmt;
+
+// Fractional digits of the fixed-point representation (1 unit = 1e-18)
+pub const DECIMAL_SCALE: u32 = 18;
+const UNIT: i128 = 10i128.pow(DECIMAL_SCALE);
+
+/// Fixed-point decimal: an i128 count of 1e-18 units (range about ±1.7e20)
+/// Addition, subtraction and division by an integer are exact or rounded half-to-even,
+/// so sums don't pick up binary float drift (0.1 + 0.2 is exactly 0.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal(i128);
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal(0);
+
+    /// The decimal a source meant: the shortest representation that round-trips to `value`
+    /// (e.g. 0.1, not 0.1000000000000000055511151231257827)
+    pub fn from_f64(value: f64) -> Result<Decimal, String> {
+        if !value.is_finite() {
+            return Err(format!("{} has no decimal value", value));
+        }
+        // Display never uses an exponent, so this is always "[-]digits[.digits]"
+        Decimal::parse(&format!("{}", value))
+    }
+
+    /// Parse "[-]digits[.digits]", rounding half-to-even past DECIMAL_SCALE fractional digits
+    pub fn parse(text: &str) -> Result<Decimal, String> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if int_part.is_empty() || !all_digits(int_part) || !all_digits(frac_part) {
+            return Err(format!("'{}' is not a decimal number", text));
+        }
+
+        let overflow = || format!("{} is outside the decimal range (about ±1.7e20)", text);
+        let int_units = int_part.parse::<i128>()
+            .ok()
+            .and_then(|int| int.checked_mul(UNIT))
+            .ok_or_else(overflow)?;
+
+        let scale = DECIMAL_SCALE as usize;
+        let (kept, rest) = frac_part.split_at(frac_part.len().min(scale));
+        let mut frac_units: i128 = format!("{:0<width$}", kept, width = scale).parse().unwrap_or(0);
+
+        // Digits beyond the scale: round half to even on the last kept digit
+        let round_up = match rest.as_bytes().first() {
+            Some(b'6'..=b'9') => true,
+            Some(b'5') => rest[1..].bytes().any(|b| b != b'0') || frac_units % 2 == 1,
+            _ => false,
+        };
+        if round_up {
+            frac_units += 1;
+        }
+
+        let units = int_units.checked_add(frac_units).ok_or_else(overflow)?;
+        Ok(Decimal(if negative { -units } else { units }))
+    }
+
+    /// Nearest f64 to this decimal
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn checked_add(self, other: Decimal) -> Result<Decimal, String> {
+        self.0.checked_add(other.0).map(Decimal).ok_or_else(|| "decimal overflow in sum".to_string())
+    }
+
+    pub fn checked_sub(self, other: Decimal) -> Result<Decimal, String> {
+        self.0.checked_sub(other.0).map(Decimal).ok_or_else(|| "decimal overflow in difference".to_string())
+    }
+
+    /// Divide by a positive integer, rounding half to even
+    pub fn div_int(self, divisor: i128) -> Decimal {
+        Decimal(div_half_even(self.0, divisor))
+    }
+
+    /// Round half to even to `places` fractional digits
+    pub fn round_dp(self, places: u32) -> Decimal {
+        if places >= DECIMAL_SCALE {
+            return self;
+        }
+        let step = 10i128.pow(DECIMAL_SCALE - places);
+        Decimal(div_half_even(self.0, step) * step)
+    }
+}
+
+/// `numerator / divisor` (divisor > 0) rounded half to even
+fn div_half_even(numerator: i128, divisor: i128) -> i128 {
+    let quotient = numerator / divisor;
+    let remainder = (numerator % divisor).abs();
+    let away = match (remainder * 2).cmp(&divisor) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Equal => quotient % 2 != 0,
+        std::cmp::Ordering::Less => false,
+    };
+
+    match (away, numerator < 0) {
+        (true, true) => quotient - 1,
+        (true, false) => quotient + 1,
+        (false, _) => quotient,
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let int_part = units / UNIT as u128;
+        let frac_part = units % UNIT as u128;
+
+        if frac_part == 0 {
+            return write!(f, "{}{}", sign, int_part);
+        }
+        let frac = format!("{:0width$}", frac_part, width = DECIMAL_SCALE as usize);
+        write!(f, "{}{}.{}", sign, int_part, frac.trim_end_matches('0'))
+    }
+}
@@ -0,0 +1,154 @@
+use std::env;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Log level, ordered from most to least verbose
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+        }
+    }
+}
+
+// Environment variables holding secrets that must never reach the logs
+const SECRET_ENV_VARS: &[&str] = &[
+    "API_KEY",
+    "COINGECKO_API_KEY",
+    "COINGECKO_API_KEYS",
+    "COINMARKETCAP_API_KEY",
+    "COINMARKETCAP_API_KEYS",
+    "TWELVEDATA_API_KEY",
+    "TWELVEDATA_API_KEYS",
+    "MESSARI_API_KEY",
+    "MESSARI_API_KEYS",
+    "ORACLE_ED25519_KEY",
+];
+
+/// Minimum level from ORACLE_LOG=debug|info|warn|error|off (default: warn)
+fn min_level() -> Option<Level> {
+    static MIN_LEVEL: OnceLock<Option<Level>> = OnceLock::new();
+
+    *MIN_LEVEL.get_or_init(|| {
+        match env::var("ORACLE_LOG").unwrap_or_default().to_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "error" => Some(Level::Error),
+            "off" => None,
+            _ => Some(Level::Warn),
+        }
+    })
+}
+
+/// Write `LEVEL ts source msg` to stderr if the level is enabled (stdout is left untouched)
+pub fn log(level: Level, source: &str, msg: &str) {
+    match min_level() {
+        Some(min) if level >= min => {}
+        _ => return,
+    }
+
+    let ts = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    eprintln!("{} {} {} {}", level.as_str(), ts, source, redact(msg));
+}
+
+pub fn debug(source: &str, msg: &str) {
+    log(Level::Debug, source, msg);
+}
+
+pub fn info(source: &str, msg: &str) {
+    log(Level::Info, source, msg);
+}
+
+pub fn warn(source: &str, msg: &str) {
+    log(Level::Warn, source, msg);
+}
+
+pub fn error(source: &str, msg: &str) {
+    log(Level::Error, source, msg);
+}
+
+/// Redact secrets: values of key/token query params and any known secret env var values
+/// Example: "...?symbol=BTC&apikey=abc123 failed" -> "...?symbol=BTC&apikey=*** failed"
+pub fn redact(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+
+    // Query params whose name looks like a credential; the value ends at whitespace, '&' or '#'
+    let mut rest = text;
+    while let Some(eq) = rest.find('=') {
+        let (name, value) = (&rest[..eq], &rest[eq + 1..]);
+        redacted.push_str(&rest[..=eq]);
+
+        if is_secret_param(name) {
+            let end = value.find(|c: char| c.is_whitespace() || c == '&' || c == '#').unwrap_or(value.len());
+            redacted.push_str("***");
+            rest = &value[end..];
+        } else {
+            rest = value;
+        }
+    }
+    redacted.push_str(rest);
+
+    // Literal secret values wherever they appear (headers, bodies, error messages)
+    for var in SECRET_ENV_VARS {
+        if let Ok(value) = env::var(var) {
+            for secret in value.split(',').map(|s| s.trim()).filter(|s| s.len() >= 4) {
+                redacted = redacted.replace(secret, "***");
+            }
+        }
+    }
+
+    redacted
+}
+
+/// Check whether the last query param name before '=' looks like a credential
+fn is_secret_param(prefix: &str) -> bool {
+    let name = prefix
+        .rsplit(|c: char| matches!(c, '?' | '&' | '#') || c.is_whitespace())
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+
+    name.contains("key") || name.contains("token") || name.contains("secret")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_secrets_are_redacted_without_the_rest_of_the_message() {
+        assert_eq!(
+            redact("https://api.example.com/price?symbol=BTC&apikey=abc123 failed: HTTP 500"),
+            "https://api.example.com/price?symbol=BTC&apikey=*** failed: HTTP 500"
+        );
+        assert_eq!(
+            redact("/v1?x_cg_pro_api_key=k1&ids=bitcoin#top token=t2"),
+            "/v1?x_cg_pro_api_key=***&ids=bitcoin#top token=***"
+        );
+        assert_eq!(redact("a=1&b=2"), "a=1&b=2");
+    }
+
+    #[test]
+    fn secret_env_values_are_redacted_anywhere() {
+        env::set_var("MESSARI_API_KEYS", "messari-secret-1, messari-secret-2");
+        assert_eq!(
+            redact("header x-messari-api-key: messari-secret-2 rejected"),
+            "header x-messari-api-key: *** rejected"
+        );
+    }
+}
@@ -1,19 +1,108 @@
 mod aggregation;
+mod cache;
+mod decimal;
+mod logging;
+mod merkle;
+mod signing;
 mod sources;
+mod state;
+mod timefmt;
 mod types;
+mod validation;
 
-use sources::fetch_price_with_config;
+use cache::SourceCache;
+use sources::{fetch_price_with_config, prefetch_batches, BatchResults};
+use sha2::{Digest, Sha256};
 use types::*;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::env;
 use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// API keys for one source, rotated round-robin across fetches within an invocation
+struct KeyRing {
+    keys: Vec<String>,
+    next: Cell<usize>,
+}
+
+impl KeyRing {
+    /// Load a comma-separated key list (e.g. COINGECKO_API_KEYS),
+    /// falling back to the single-key variable (e.g. COINGECKO_API_KEY)
+    fn from_env(list_var: &str, single_var: &str) -> Self {
+        let keys: Vec<String> = match env::var(list_var) {
+            Ok(list) => list
+                .split(',')
+                .map(|k| k.trim().to_string())
+                .filter(|k| !k.is_empty())
+                .collect(),
+            Err(_) => env::var(single_var).ok().into_iter().collect(),
+        };
+
+        KeyRing { keys, next: Cell::new(0) }
+    }
+
+    /// Pick the next key; the sequence depends only on fetch order, so runs are reproducible
+    fn next_key(&self) -> Option<&str> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        let index = self.next.get();
+        self.next.set(index + 1);
+        Some(self.keys[index % self.keys.len()].as_str())
+    }
+}
+
+/// API keys for all sources that need them
+struct ApiKeys {
+    coingecko: KeyRing,
+    coinmarketcap: KeyRing,
+    twelvedata: KeyRing,
+    messari: KeyRing,
+}
+
+impl ApiKeys {
+    /// Next key for a (resolved) source name, if that source takes one
+    fn for_source(&self, source_name: &str) -> Option<&str> {
+        match source_name {
+            "coingecko" => self.coingecko.next_key(),
+            "coinmarketcap" => self.coinmarketcap.next_key(),
+            "twelvedata" => self.twelvedata.next_key(),
+            "messari" => self.messari.next_key(),
+            _ => None,
+        }
+    }
+}
+
+/// Shared inputs for processing every request of one invocation
+struct Invocation<'a> {
+    max_deviation: f64,
+    batch: &'a BatchResults,
+    config: &'a ExecutionConfig,
+    api_keys: &'a ApiKeys,
+    /// Overall deadline (total_deadline_secs)
+    deadline: Option<Instant>,
+    /// USDT/USD rate when normalize_stablecoins is on
+    usdt_usd_rate: Option<Result<f64, String>>,
+    /// config.shared_rates by id, resolved before the first request
+    shared_rates: HashMap<String, Result<f64, String>>,
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Execution budget starts before anything else
+    let started_at = Instant::now();
+
     // Read input from stdin
     let mut input_string = String::new();
     io::stdin().read_to_string(&mut input_string)?;
 
     // Parse JSON request
-    let request: OracleRequest = serde_json::from_str(&input_string)?;
+    let request: OracleRequest = serde_json::from_str(&input_string).map_err(|e| {
+        logging::error("main", &format!("Invalid request JSON: {}", e));
+        e
+    })?;
 
     // Validate: check max tokens limit
     if request.requests.len() > MAX_TOKENS_PER_REQUEST {
@@ -27,97 +116,871 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    // Signing key for on-chain verification (a malformed key fails before any fetch)
+    let signing_key = signing::signing_key_from_env().inspect_err(|e| {
+        logging::error("signing", &e.to_string());
+    })?;
+
+    // History mode: answer from the state file without any network calls
+    if request.mode == Mode::History {
+        let mut oracle_response = process_history_request(&request)?;
+        if let Some(key) = &signing_key {
+            signing::sign_response(&mut oracle_response, key)?;
+        }
+        return write_response(&oracle_response, request.config.pretty_output);
+    }
+
     // Get API keys from environment (encrypted secrets)
-    let coingecko_key = env::var("COINGECKO_API_KEY").ok();
-    let coinmarketcap_key = env::var("COINMARKETCAP_API_KEY").ok();
-    let twelvedata_key = env::var("TWELVEDATA_API_KEY").ok();
+    let api_keys = ApiKeys {
+        coingecko: KeyRing::from_env("COINGECKO_API_KEYS", "COINGECKO_API_KEY"),
+        coinmarketcap: KeyRing::from_env("COINMARKETCAP_API_KEYS", "COINMARKETCAP_API_KEY"),
+        twelvedata: KeyRing::from_env("TWELVEDATA_API_KEYS", "TWELVEDATA_API_KEY"),
+        messari: KeyRing::from_env("MESSARI_API_KEYS", "MESSARI_API_KEY"),
+    };
+
+    // Probe mode: time every source without aggregating
+    if request.mode == Mode::Probe {
+        let oracle_response = process_probe_request(&request, &api_keys, started_at);
+        return write_response(&oracle_response, request.config.pretty_output);
+    }
+
+    // Validate up front so guaranteed failures make no HTTP calls
+    let duplicates = validation::duplicate_id_occurrences(&request.requests);
+    let validation: Vec<Result<(), String>> = request.requests.iter()
+        .zip(&duplicates)
+        .map(|(data_req, duplicate)| match duplicate {
+            Some(_) if request.config.duplicate_ids == DuplicateIds::Reject => {
+                Err(format!("Invalid request: duplicate id '{}'", data_req.id))
+            }
+            _ => validation::validate_request(data_req, &request.config),
+        })
+        .collect();
+    let valid_requests: Vec<&DataRequest> = request.requests.iter()
+        .zip(&validation)
+        .filter(|(_, check)| check.is_ok())
+        .map(|(data_req, _)| data_req)
+        .collect();
+
+    // Overall deadline: sources not started by then are skipped, retries never sleep past it
+    let deadline = request.config.total_deadline_secs
+        .map(|secs| started_at + Duration::from_secs(secs));
+
+    // Disk-backed per-source cache (an unreadable file starts empty)
+    let mut cache = request.config.cache_path.as_ref().map(|path| {
+        SourceCache::load(path).unwrap_or_else(|e| {
+            logging::warn("cache", &format!("Ignoring cache: {}", e));
+            SourceCache::default()
+        })
+    });
+
+    // Coalesce multi-id sources (e.g. CoinGecko ids=a,b,c) into single calls, named outputs included
+    // Sources a fresh cache entry already answers are left out, so they make no HTTP call
+    let output_requests: Vec<DataRequest> = valid_requests.iter()
+        .flat_map(|data_req| data_req.outputs.iter().map(|output| data_req.output_request(output)))
+        .collect();
+    let prefetch_requests: Vec<DataRequest> = valid_requests.iter().copied()
+        .chain(&output_requests)
+        .chain(&request.config.shared_rates)
+        .map(|data_req| without_cached_sources(data_req, cache.as_ref(), &request.config))
+        .collect();
+    let batch = prefetch_batches(
+        &prefetch_requests.iter().collect::<Vec<_>>(),
+        api_keys.coingecko.next_key(),
+        api_keys.twelvedata.next_key(),
+        &request.config,
+        deadline,
+    );
+
+    // USDT/USD rate for normalize_stablecoins, fetched once per invocation
+    let usdt_usd_rate = if request.config.normalize_stablecoins {
+        Some(fetch_usdt_usd_rate(&request.config, &batch, &api_keys, deadline))
+    } else {
+        None
+    };
+
+    // Reference time for snapshot mode (batch start)
+    let snapshot_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let mut run = Invocation {
+        max_deviation: request.max_price_deviation_percent,
+        batch: &batch,
+        config: &request.config,
+        api_keys: &api_keys,
+        deadline,
+        usdt_usd_rate,
+        shared_rates: HashMap::new(),
+    };
+
+    // Dependency phase: each shared cross-rate is aggregated once so every request converts consistently
+    run.shared_rates = resolve_shared_rates(&run, cache.as_mut());
+
+    // Last stored values for monotonic feeds (unreadable state means nothing to compare against)
+    let last_state = match &request.config.state_path {
+        Some(path) if request.requests.iter().any(|r| r.monotonic) => {
+            state::OracleState::load(path).unwrap_or_else(|e| {
+                logging::warn("state", &format!("Ignoring state for monotonic check: {}", e));
+                state::OracleState::default()
+            })
+        }
+        _ => state::OracleState::default(),
+    };
 
     let mut data_responses = Vec::new();
 
     // Process each token sequentially
-    for data_req in request.requests {
-        let response = process_data_request(
-            &data_req,
-            request.max_price_deviation_percent,
-            coingecko_key.as_deref(),
-            coinmarketcap_key.as_deref(),
-            twelvedata_key.as_deref(),
-        );
+    for (data_req, check) in request.requests.iter().zip(validation) {
+        if let Err(e) = check {
+            data_responses.push(DataResponse::failed(&data_req.id, e));
+            continue;
+        }
+
+        let response = if let Some(discovery) = &data_req.discovery {
+            process_discovery_request(data_req, discovery, &run, cache.as_mut())
+        } else if data_req.outputs.is_empty() {
+            process_data_request(data_req, &run, cache.as_mut())
+        } else {
+            process_named_outputs(data_req, &run, cache.as_mut())
+        };
+
+        // A stale or forked node can serve a value from before the last report
+        let last = last_state.last_samples(&data_req.id, 1).first().map(|sample| sample.value);
+        let response = apply_monotonic_guard(response, data_req, last);
 
         data_responses.push(response);
     }
 
+    // Make responses for shared ids distinguishable
+    for (response, duplicate) in data_responses.iter_mut().zip(&duplicates) {
+        let occurrence = match duplicate {
+            Some(occurrence) => *occurrence,
+            None => continue,
+        };
+
+        match request.config.duplicate_ids {
+            DuplicateIds::Warn => {
+                response.append_message(format!("Warning: duplicate id (occurrence {})", occurrence));
+            }
+            DuplicateIds::Suffix if occurrence > 1 => {
+                response.id = format!("{}#{}", response.id, occurrence);
+            }
+            _ => {}
+        }
+    }
+
+    // Snapshot: every asset must be priced near the same moment
+    if request.snapshot {
+        apply_snapshot_skew(&mut data_responses, snapshot_time, &request);
+    }
+
+    // Human-readable timestamps, set after snapshot checks and before signing
+    if request.config.include_iso_timestamp {
+        for data in data_responses.iter_mut().filter_map(|r| r.data.as_mut()) {
+            data.timestamp_iso = Some(timefmt::format_rfc3339(data.timestamp));
+        }
+    }
+
+    // Persist fresh source values for later invocations
+    if let (Some(path), Some(cache)) = (&request.config.cache_path, &cache) {
+        if let Err(e) = cache.save(path) {
+            logging::warn("cache", &format!("Failed to update cache: {}", e));
+        }
+    }
+
+    // Persist numeric aggregates to the state file history
+    if let Some(path) = &request.config.state_path {
+        if let Err(e) = record_history(path, &data_responses) {
+            logging::warn("state", &format!("Failed to update state: {}", e));
+        }
+    }
+
+    // Hash the raw input so the caller can verify exactly what was processed
+    let request_hash = if request.config.echo_request {
+        Some(hex::encode(Sha256::digest(input_string.as_bytes())))
+    } else {
+        None
+    };
+
     // Build response
-    let oracle_response = OracleResponse {
+    let effective_config = if request.config.echo_effective_config {
+        Some(EffectiveConfig::new(&request))
+    } else {
+        None
+    };
+
+    let mut oracle_response = OracleResponse {
         results: data_responses,
+        effective_config,
+        request_hash,
+        ed25519_signature: None,
+        ed25519_pubkey: None,
     };
 
+    // Sign last so the signature covers everything else in the output
+    if let Some(key) = &signing_key {
+        signing::sign_response(&mut oracle_response, key)?;
+    }
+
     // Output JSON response to stdout
-    let output = serde_json::to_string(&oracle_response)?;
-    print!("{}", output);
+    write_response(&oracle_response, request.config.pretty_output)
+}
+
+/// Print the response as compact JSON, or indented when `pretty_output` is set (debugging)
+fn write_response(oracle_response: &OracleResponse, pretty: bool) -> Result<(), Box<dyn std::error::Error>> {
+    print!("{}", render_response(oracle_response, pretty)?);
     io::stdout().flush()?;
 
     Ok(())
 }
 
+/// Fail (or degrade) a monotonic result that is below the last stored value
+fn apply_monotonic_guard(mut response: DataResponse, data_req: &DataRequest, last: Option<f64>) -> DataResponse {
+    let value = match (&response.data, last) {
+        (Some(data), Some(_)) if data_req.monotonic => data.value.as_number(),
+        _ => None,
+    };
+    let (Some(value), Some(last)) = (value, last) else {
+        return response;
+    };
+    if value >= last {
+        return response;
+    }
+
+    let warning = format!("Non-monotonic value: {} is below the last stored {}", value, last);
+    match data_req.monotonic_action {
+        MonotonicAction::Reject => DataResponse::failed(&data_req.id, warning),
+        MonotonicAction::Degrade => {
+            if let Some(data) = response.data.as_mut() {
+                data.degraded = true;
+            }
+            response.append_message(warning);
+            response
+        }
+    }
+}
+
+/// Response JSON, compact or indented; both hold the same value
+fn render_response(oracle_response: &OracleResponse, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(oracle_response)
+    } else {
+        serde_json::to_string(oracle_response)
+    }
+}
+
+/// Copy of a request without the sources that have a fresh cache entry
+fn without_cached_sources(data_req: &DataRequest, cache: Option<&SourceCache>, config: &ExecutionConfig) -> DataRequest {
+    let mut data_req = data_req.clone();
+    let Some(cache) = cache else {
+        return data_req;
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let cache_ttl = config.cache_ttl_secs.unwrap_or(DEFAULT_CACHE_TTL_SECS);
+    data_req.sources.retain(|source| {
+        let id = source.id.as_ref().unwrap_or(&data_req.id);
+        let key = SourceCache::key(config.resolve_source_name(&source.name), source, id);
+        cache.get(&key, now, cache_ttl).is_none()
+    });
+    data_req
+}
+
+/// Fetch the USDT/USD rate from the configured source (default: CoinGecko "tether")
+fn fetch_usdt_usd_rate(config: &ExecutionConfig, batch: &BatchResults, api_keys: &ApiKeys, deadline: Option<Instant>) -> Result<f64, String> {
+    let default_source = PriceSource::builtin("coingecko", "tether");
+    let source = config.stablecoin_rate_source.as_ref().unwrap_or(&default_source);
+    let id = source.id.as_deref().unwrap_or("tether");
+    let api_key = api_keys.for_source(config.resolve_source_name(&source.name));
+
+    let price = fetch_price_with_config(source, id, api_key, batch, config, deadline)
+        .map_err(|e| format!("{}: {}", source.name, e))?;
+
+    match price.value.as_number() {
+        Some(rate) if rate > 0.0 => Ok(rate),
+        _ => Err(format!("{}: invalid USDT/USD rate {:?}", source.name, price.value)),
+    }
+}
+
+/// Aggregate every config.shared_rates entry into a positive rate, or the reason it is unavailable
+fn resolve_shared_rates(run: &Invocation, mut cache: Option<&mut SourceCache>) -> HashMap<String, Result<f64, String>> {
+    run.config.shared_rates.iter()
+        .map(|rate_req| {
+            let rate = validation::validate_request(rate_req, run.config).and_then(|()| {
+                let response = process_data_request(rate_req, run, cache.as_deref_mut());
+                match response.data.as_ref().and_then(|data| data.value.as_number()) {
+                    Some(rate) if rate > 0.0 => Ok(rate),
+                    Some(rate) => Err(format!("invalid rate {}", rate)),
+                    None => Err(response.message.unwrap_or_else(|| "no numeric value".to_string())),
+                }
+            });
+            if let Err(e) = &rate {
+                logging::warn("shared_rates", &format!("{} unavailable: {}", rate_req.id, e));
+            }
+            (rate_req.id.clone(), rate)
+        })
+        .collect()
+}
+
+/// Reject or flag results whose freshest source is more than `snapshot_skew_secs` from the batch start
+fn apply_snapshot_skew(responses: &mut [DataResponse], reference: u64, request: &OracleRequest) {
+    for response in responses.iter_mut() {
+        let skew = match &response.data {
+            Some(data) => data.timestamp.abs_diff(reference),
+            None => continue,
+        };
+        if skew <= request.snapshot_skew_secs {
+            continue;
+        }
+
+        let warning = format!(
+            "Snapshot skew {}s exceeds {}s",
+            skew, request.snapshot_skew_secs
+        );
+        match request.snapshot_skew_action {
+            SkewAction::Reject => *response = DataResponse::failed(&response.id, warning),
+            SkewAction::Degrade => {
+                if let Some(data) = response.data.as_mut() {
+                    data.degraded = true;
+                }
+                response.append_message(warning);
+            }
+        }
+    }
+}
+
+/// Build a response from stored history (no network calls)
+fn process_history_request(request: &OracleRequest) -> Result<OracleResponse, Box<dyn std::error::Error>> {
+    let path = request.config.state_path.as_ref()
+        .ok_or("History mode requires config.state_path")?;
+    let state = state::OracleState::load(path)?;
+    let count = request.history_count.min(state::MAX_HISTORY_SAMPLES);
+
+    let results = request.requests.iter()
+        .map(|data_req| {
+            let samples = state.last_samples(&data_req.id, count);
+            DataResponse {
+                id: data_req.id.clone(),
+                data: None,
+                message: if samples.is_empty() { Some("No history stored".to_string()) } else { None },
+                history: Some(samples),
+                probe: None,
+            }
+        })
+        .collect();
+
+    Ok(OracleResponse {
+        results,
+        effective_config: None,
+        request_hash: None,
+        ed25519_signature: None,
+        ed25519_pubkey: None,
+    })
+}
+
+/// Call every configured source `probe_iterations` times and report min/avg/max latency
+fn process_probe_request(request: &OracleRequest, api_keys: &ApiKeys, started_at: Instant) -> OracleResponse {
+    let config = &request.config;
+    let iterations = request.probe_iterations.clamp(1, MAX_PROBE_ITERATIONS);
+    let no_batch = BatchResults::new();
+    let deadline = config.total_deadline_secs.map(|secs| started_at + Duration::from_secs(secs));
+    let mut first_call = true;
+
+    let results = request.requests.iter()
+        .map(|data_req| {
+            // Malformed requests fail with the same error as in fetch mode
+            if let Err(e) = validation::validate_request(data_req, config) {
+                return DataResponse::failed(&data_req.id, e);
+            }
+
+            let sources = data_req.sources.iter()
+                .chain(data_req.outputs.iter().flat_map(|output| &output.sources));
+
+            let probe = sources
+                .map(|source_config| {
+                    let id = source_config.id.as_ref().unwrap_or(&data_req.id);
+                    let api_key = api_keys.for_source(config.resolve_source_name(&source_config.name));
+                    let mut latencies: Vec<u64> = Vec::new();
+                    let mut error = None;
+
+                    for _ in 0..iterations {
+                        // Calls are spaced by stagger_ms and stop at the overall deadline
+                        if !std::mem::take(&mut first_call) {
+                            stagger(config, deadline);
+                        }
+                        if deadline.is_some_and(|d| Instant::now() >= d) {
+                            error = Some("deadline exceeded".to_string());
+                            continue;
+                        }
+
+                        let started = Instant::now();
+                        match fetch_price_with_config(source_config, id, api_key, &no_batch, config, deadline) {
+                            Ok(_) => latencies.push(started.elapsed().as_millis() as u64),
+                            Err(e) => error = Some(e.to_string()),
+                        }
+                    }
+
+                    ProbeStats {
+                        source: source_config.label.clone().unwrap_or_else(|| source_config.name.clone()),
+                        ok: latencies.len(),
+                        failed: iterations - latencies.len(),
+                        min_ms: latencies.iter().min().copied(),
+                        avg_ms: (!latencies.is_empty())
+                            .then(|| latencies.iter().sum::<u64>() / latencies.len() as u64),
+                        max_ms: latencies.iter().max().copied(),
+                        error,
+                    }
+                })
+                .collect();
+
+            DataResponse {
+                id: data_req.id.clone(),
+                data: None,
+                message: None,
+                history: None,
+                probe: Some(probe),
+            }
+        })
+        .collect();
+
+    OracleResponse {
+        results,
+        effective_config: None,
+        request_hash: None,
+        ed25519_signature: None,
+        ed25519_pubkey: None,
+    }
+}
+
+/// Sleep `stagger_ms` before the next source fetch, clipped so it never runs past the deadline
+fn stagger(config: &ExecutionConfig, deadline: Option<Instant>) {
+    if let Some(stagger_ms) = config.stagger_ms {
+        let mut delay = Duration::from_millis(stagger_ms);
+        if let Some(deadline) = deadline {
+            delay = delay.min(deadline.saturating_duration_since(Instant::now()));
+        }
+        thread::sleep(delay);
+    }
+}
+
+/// Append successful numeric results to the persisted history
+/// Degraded results (e.g. a monotonic regression kept with `degrade`) are not recorded, so they
+/// never become the reference for the next run
+fn record_history(path: &str, responses: &[DataResponse]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut state = state::OracleState::load(path)?;
+
+    for response in responses {
+        if let Some(data) = response.data.as_ref().filter(|data| !data.degraded) {
+            if let DataValue::Number(value) = data.value {
+                state.record(&response.id, data.timestamp, value);
+            }
+        }
+    }
+
+    state.save(path)
+}
+
+/// Two-phase fetch: list source ids from the directory, then aggregate a template source per id
+fn process_discovery_request(
+    data_req: &DataRequest,
+    discovery: &DiscoveryConfig,
+    run: &Invocation,
+    cache: Option<&mut SourceCache>,
+) -> DataResponse {
+    // The directory call counts against the same overall deadline as the sources it yields
+    if run.deadline.is_some_and(|d| Instant::now() >= d) {
+        return DataResponse::failed(&data_req.id, "Discovery failed: deadline exceeded".to_string());
+    }
+
+    let ids = match sources::fetch_discovery_list(&discovery.directory, run.config, run.deadline) {
+        Ok(ids) => ids,
+        Err(e) => return DataResponse::failed(&data_req.id, format!("Discovery failed: {}", e)),
+    };
+
+    let cap = discovery.max_sources.unwrap_or(DEFAULT_MAX_DISCOVERED).min(MAX_DISCOVERED_SOURCES);
+    let mut expanded = data_req.clone();
+    expanded.sources.extend(ids.iter().take(cap).map(|id| discovery.source_for(id)));
+    expanded.discovery = None;
+    logging::info("discovery", &format!("{} ids listed, fetching {}", ids.len(), ids.len().min(cap)));
+
+    if expanded.sources.is_empty() {
+        return DataResponse::failed(&data_req.id, "Discovery failed: directory listed no sources".to_string());
+    }
+
+    let mut response = process_data_request(&expanded, run, cache);
+    if ids.len() > cap {
+        response.append_message(format!("Discovered {} sources, used the first {}", ids.len(), cap));
+    }
+    response
+}
+
+/// Process each named output as its own request and combine them into one response
+fn process_named_outputs(
+    data_req: &DataRequest,
+    run: &Invocation,
+    mut cache: Option<&mut SourceCache>,
+) -> DataResponse {
+    let mut outputs: Vec<NamedValue> = Vec::new();
+    let mut primary: Option<PriceData> = None;
+    let mut sources: Vec<String> = Vec::new();
+    let mut degraded = false;
+    let mut failed: Vec<&str> = Vec::new();
+
+    for output in &data_req.outputs {
+        let response = process_data_request(&data_req.output_request(output), run, cache.as_deref_mut());
+        let mut named = NamedValue {
+            name: output.name.clone(),
+            value: None,
+            sources: Vec::new(),
+            message: response.message,
+        };
+
+        match (response.data, &output.value_type) {
+            (Some(data), Some(value_type)) if !data.value.is_type(value_type) => {
+                named.message = Some(format!("Expected a {} value", format!("{:?}", value_type).to_lowercase()));
+            }
+            (Some(data), _) => {
+                named.value = Some(data.value.clone());
+                named.sources = data.sources.clone();
+                degraded |= data.degraded;
+                for source in &data.sources {
+                    if !sources.contains(source) {
+                        sources.push(source.clone());
+                    }
+                }
+                primary.get_or_insert(data);
+            }
+            (None, _) => {}
+        }
+
+        if named.value.is_none() {
+            failed.push(&output.name);
+        }
+        outputs.push(named);
+    }
+
+    // Top-level fields mirror the first successful output; each output keeps its own value
+    let Some(primary) = primary else {
+        let summary: Vec<String> = outputs.iter()
+            .map(|o| format!("{}: {}", o.name, o.message.as_deref().unwrap_or("failed")))
+            .collect();
+        return DataResponse::failed(&data_req.id, format!("All outputs failed ({})", summary.join("; ")));
+    };
+
+    let message = if failed.is_empty() {
+        None
+    } else {
+        Some(format!("Outputs failed: {}", failed.join(", ")))
+    };
+
+    DataResponse {
+        id: data_req.id.clone(),
+        data: Some(PriceData {
+            sources,
+            degraded,
+            outputs: Some(outputs),
+            ..primary
+        }),
+        message,
+        history: None,
+        probe: None,
+    }
+}
+
 /// Process single token request
 fn process_data_request(
     data_req: &DataRequest,
-    max_deviation: f64,
-    coingecko_key: Option<&str>,
-    coinmarketcap_key: Option<&str>,
-    twelvedata_key: Option<&str>,
+    run: &Invocation,
+    mut cache: Option<&mut SourceCache>,
 ) -> DataResponse {
+    let Invocation { batch, config, api_keys, deadline, .. } = *run;
+    let max_deviation = data_req.max_deviation(run.max_deviation);
+    let usdt_usd_rate = run.usdt_usd_rate.as_ref();
     let mut source_prices: Vec<SourcePrice> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
+    let mut timed_out = false;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let cache_ttl = config.cache_ttl_secs.unwrap_or(DEFAULT_CACHE_TTL_SECS);
+
+    // Audit trail: every filter stage below records which sources it removed and why
+    let mut dropped: Vec<DroppedSource> = Vec::new();
+
+    // Canaries are fetched like any source but kept out of the aggregate
+    let mut canary_prices: Vec<SourcePrice> = Vec::new();
+    let mut canary_errors: Vec<String> = Vec::new();
+
+    // A source that returns a type other than its declared value_type is dropped, not skipped silently
+    let mut accept_typed = |source_config: &PriceSource, price: SourcePrice| {
+        match validation::check_value_type(&price, source_config.value_type.as_ref()) {
+            Ok(()) if source_config.canary => canary_prices.push(price),
+            Ok(()) => source_prices.push(price),
+            Err(reason) => {
+                logging::warn(&price.source_name, &format!("dropped: {}", reason));
+                dropped.push(DroppedSource::new(&price.source_name, reason.to_string()));
+            }
+        }
+    };
 
     // Fetch prices from all sources sequentially
-    for source_config in &data_req.sources {
-        // Determine which id to use for this source
-        let id = source_config
-            .id
-            .as_ref()
-            .unwrap_or(&data_req.id);
+    for (index, source_config) in data_req.sources.iter().enumerate() {
+        let id = source_config.id.as_ref().unwrap_or(&data_req.id);
+        let cache_key = SourceCache::key(config.resolve_source_name(&source_config.name), source_config, id);
+
+        // Deployment policy covers cached values too: a since-denied source is not served from the cache
+        // Canary failures are reported only as canary warnings, never as request errors
+        if let Err(e) = sources::check_source_policy(source_config, config) {
+            if source_config.canary {
+                canary_errors.push(format!("{}: {}", source_config.name, e));
+            } else {
+                errors.push(format!("{}: {}", source_config.name, e));
+            }
+            continue;
+        }
+
+        // Serve slow-moving feeds from the cache while fresh enough (no HTTP call, no stagger)
+        if let Some(mut price) = cache.as_deref().and_then(|c| c.get(&cache_key, now, cache_ttl)) {
+            logging::info(&source_config.name, &format!("cache hit id={} value={:?}", id, price.value));
+            price.weight = source_config.weight;
+            if let Some(label) = &source_config.label {
+                price.source_name = label.clone();
+                price.detail.name = label.clone();
+            }
+            if let Some(factor) = source_config.unit_convert {
+                price.convert_units(factor);
+            }
+            accept_typed(source_config, price);
+            continue;
+        }
+
+        // Stagger successive launches to smooth the outbound burst (never past the deadline)
+        if index > 0 {
+            stagger(config, deadline);
+        }
+
+        // Skip sources that can't start before the overall deadline
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            if source_config.canary {
+                canary_errors.push(format!("{}: deadline exceeded", source_config.name));
+            } else {
+                errors.push(format!("{}: deadline exceeded", source_config.name));
+                timed_out = true;
+            }
+            continue;
+        }
 
         // Get API key for this source
-        let api_key = match source_config.name.as_str() {
-            "coingecko" => coingecko_key,
-            "coinmarketcap" => coinmarketcap_key,
-            "twelvedata" => twelvedata_key,
-            _ => None,
-        };
+        let api_key = api_keys.for_source(config.resolve_source_name(&source_config.name));
 
         // Fetch price from source (with custom config support)
-        match fetch_price_with_config(&source_config.name, id, api_key, source_config.custom.as_ref()) {
-            Ok(price) => source_prices.push(price),
-            Err(e) => errors.push(format!("{}: {}", source_config.name, e)),
+        logging::debug(&source_config.name, &format!("start id={}", id));
+        let started = Instant::now();
+
+        match fetch_price_with_config(source_config, id, api_key, batch, config, deadline) {
+            Ok(mut price) => {
+                price.weight = source_config.weight;
+                logging::info(
+                    &source_config.name,
+                    &format!("ok id={} in {}ms value={:?}", id, started.elapsed().as_millis(), price.value),
+                );
+                if let Some(cache) = cache.as_deref_mut() {
+                    cache.insert(cache_key, &price, now);
+                }
+                // Convert after caching so the cache keeps the source's native unit
+                if let Some(factor) = source_config.unit_convert {
+                    price.convert_units(factor);
+                }
+                accept_typed(source_config, price);
+            }
+            Err(e) => {
+                logging::warn(
+                    &source_config.name,
+                    &format!("failed id={} in {}ms: {}", id, started.elapsed().as_millis(), e),
+                );
+                if source_config.canary {
+                    canary_errors.push(format!("{}: {}", source_config.name, e));
+                } else {
+                    errors.push(format!("{}: {}", source_config.name, e));
+                }
+            }
         }
     }
 
+    // A source contributes only if it passes every gate; the reason for each drop is reported
+    source_prices.retain(|p| match validation::accept_source(p, &data_req.source_gates, now) {
+        Ok(()) => true,
+        Err(reason) => {
+            logging::warn(&p.source_name, &format!("dropped: {}", reason));
+            dropped.push(DroppedSource::new(&p.source_name, reason.to_string()));
+            false
+        }
+    });
+
+    // Discrete freshness tiers: each source takes its tier's weight, older than the last tier it is dropped
+    if !data_req.staleness_tiers.is_empty() {
+        source_prices.retain_mut(|p| match validation::staleness_tier(p, &data_req.staleness_tiers, now) {
+            Ok((tier, weight)) => {
+                p.tier_weight = Some(weight);
+                p.detail.tier = Some(tier);
+                true
+            }
+            Err(reason) => {
+                logging::warn(&p.source_name, &format!("dropped: {}", reason));
+                dropped.push(DroppedSource::new(&p.source_name, reason.to_string()));
+                false
+            }
+        });
+    }
+
     // Check if we have enough successful responses
     if source_prices.len() < data_req.min_sources_num {
-        let error_msg = format!(
+        let mut error_msg = format!(
             "Not enough sources responded ({}/{}). Errors: {}",
             source_prices.len(),
             data_req.min_sources_num,
             errors.join(", ")
         );
+        if !dropped.is_empty() {
+            error_msg = format!("{}. Dropped: {}", error_msg, DroppedSource::summary(&dropped));
+        }
+
+        return DataResponse::failed(&data_req.id, error_msg);
+    }
+
+    // Deadline cut some sources off: fail unless partial aggregation is allowed
+    if timed_out && config.timeout_behavior == TimeoutBehavior::FailAll {
+        let error_msg = format!("Deadline exceeded. Errors: {}", errors.join(", "));
+        return DataResponse::failed(&data_req.id, error_msg);
+    }
+
+    // Non-fatal warnings appended to the final message
+    let mut warnings: Vec<String> = Vec::new();
 
-        return DataResponse {
-            id: data_req.id.clone(),
-            data: None,
-            message: Some(error_msg),
+    // Suspiciously rounded values hint at a cached or degraded upstream
+    if let Some(min_digits) = data_req.min_significant_digits {
+        let is_coarse = |p: &SourcePrice| {
+            p.value.as_number().is_some_and(|n| aggregation::significant_digits(n) < min_digits)
         };
+        let coarse: Vec<String> = source_prices.iter()
+            .filter(|p| is_coarse(p))
+            .map(|p| p.source_name.clone())
+            .collect();
+
+        if !coarse.is_empty() {
+            let action = if data_req.drop_low_precision { "dropped" } else { "kept" };
+            warnings.push(format!(
+                "Low precision (< {} significant digits, {}): {}",
+                min_digits, action, coarse.join(", ")
+            ));
+
+            if data_req.drop_low_precision {
+                for name in &coarse {
+                    dropped.push(DroppedSource::new(name, format!("low precision (< {} significant digits)", min_digits)));
+                }
+                source_prices.retain(|p| !is_coarse(p));
+                if source_prices.len() < data_req.min_sources_num {
+                    let error_msg = format!(
+                        "Not enough sources after dropping low-precision values ({}/{})",
+                        source_prices.len(),
+                        data_req.min_sources_num
+                    );
+                    return DataResponse::failed(&data_req.id, error_msg);
+                }
+            }
+        }
     }
 
-    // Determine if we have numeric values for aggregation
-    let has_numeric = source_prices.iter().any(|p| p.value.as_number().is_some());
+    // Convert USDT-quoted prices to USD so a depeg doesn't bias mixed baskets
+    if let Some(rate) = usdt_usd_rate {
+        let converted: Vec<String> = source_prices.iter()
+            .filter(|p| p.currency.as_deref() == Some("USDT") && p.value.as_number().is_some())
+            .map(|p| p.source_name.clone())
+            .collect();
 
-    // Use the latest timestamp from all sources
-    let latest_timestamp = source_prices.iter().map(|p| p.timestamp).max().unwrap_or(0);
+        if !converted.is_empty() {
+            let rate = match rate {
+                Ok(rate) => *rate,
+                Err(e) => {
+                    return DataResponse::failed(&data_req.id, format!("USDT/USD rate unavailable: {}", e));
+                }
+            };
 
-    // Collect source names
-    let source_names: Vec<String> = source_prices.iter().map(|p| p.source_name.clone()).collect();
+            for price in source_prices.iter_mut().filter(|p| p.currency.as_deref() == Some("USDT")) {
+                if let Some(value) = price.value.as_number() {
+                    price.value = DataValue::Number(value * rate);
+                    price.currency = None;
+                }
+            }
+            warnings.push(format!("USDT/USD {:.6} applied to {}", rate, converted.join(", ")));
+        }
+    }
+
+    // Consensus: keep only the largest cluster of sources that agree with each other
+    let mut consensus_sources: Vec<String> = Vec::new();
+    let mut excluded_sources: Vec<String> = Vec::new();
+    if let Some(min_agree) = data_req.min_agree_sources {
+        let agreement = data_req.agreement_percent.unwrap_or(max_deviation);
+        let cluster = aggregation::find_agreement_cluster(&source_prices, agreement);
+
+        if cluster.len() < min_agree {
+            let error_msg = format!(
+                "Not enough sources agree within {:.2}% ({}/{})",
+                agreement,
+                cluster.len(),
+                min_agree
+            );
+            return DataResponse::failed(&data_req.id, error_msg);
+        }
+
+        let (agreeing, excluded): (Vec<_>, Vec<_>) = source_prices
+            .into_iter()
+            .enumerate()
+            .partition(|(i, _)| cluster.contains(i));
+
+        if !excluded.is_empty() {
+            let names: Vec<String> = excluded.iter().map(|(_, p)| p.source_name.clone()).collect();
+            warnings.push(format!("Excluded by consensus: {}", names.join(", ")));
+            for name in &names {
+                dropped.push(DroppedSource::new(name, format!("outside consensus ({:.2}%)", agreement)));
+            }
+            excluded_sources = names;
+        }
+
+        source_prices = agreeing.into_iter().map(|(_, p)| p).collect();
+        consensus_sources = source_prices.iter().map(|p| p.source_name.clone()).collect();
+    }
+
+    // Aggregating across quote currencies is almost certainly a misconfiguration
+    // USDT counts as USD here unless sources declare quotes (normalize_stablecoins converts it)
+    let explicit_quotes = data_req.sources.iter().any(|s| s.quote.is_some());
+    let mut currencies: Vec<&str> = source_prices.iter()
+        .map(|p| match p.currency.as_deref() {
+            None => "USD",
+            Some("USDT") if !explicit_quotes => "USD",
+            Some(currency) => currency,
+        })
+        .collect();
+    currencies.sort_unstable();
+    currencies.dedup();
+    if currencies.len() > 1 {
+        // Explicit per-source quotes promise a common currency after normalization
+        if explicit_quotes {
+            let error_msg = format!("Sources don't share a quote currency after normalization: {}", currencies.join(", "));
+            return DataResponse::failed(&data_req.id, error_msg);
+        }
+        warnings.push(format!("Mixed quote currencies: {}", currencies.join(", ")));
+    }
+
+    // Single-source results are only as trustworthy as that one source
+    let mut degraded = timed_out;
+    if source_prices.len() == 1 && !data_req.single_source_ok {
+        let warning = format!("Single source: {}", source_prices[0].source_name);
+        if data_req.single_source_is_error {
+            return DataResponse::failed(&data_req.id, warning);
+        }
+        warnings.push(warning);
+        degraded = true;
+    }
+
+    // Determine if we have numeric values for aggregation
+    let has_numeric = source_prices.iter().any(|p| p.value.as_number().is_some());
 
     // Build error message if any sources failed (but we still have enough)
     let message = if !errors.is_empty() {
@@ -127,55 +990,295 @@ fn process_data_request(
     };
 
     // Get final value: aggregate if numeric, otherwise take first value
-    let final_value = if has_numeric {
-        // Check price deviation for numeric values
-        let deviation = aggregation::calculate_price_deviation(&source_prices);
-        if deviation > max_deviation {
+    // Method actually used (a deviation policy may switch it)
+    let mut aggregation_method = data_req.aggregation_method.clone();
+
+    let mut vote_info: Option<String> = None;
+    let mut final_value = if data_req.aggregation_method.is_boolean() {
+        // Boolean vote (no deviation check)
+        match aggregation::aggregate_booleans(&source_prices, &data_req.aggregation_method) {
+            Ok(vote) => {
+                vote_info = Some(format!("votes: {} true / {} false", vote.true_votes, vote.false_votes));
+                types::DataValue::Boolean(vote.value)
+            }
+            Err(e) => {
+                return DataResponse::failed(&data_req.id, format!("Aggregation failed: {}", e));
+            }
+        }
+    } else if has_numeric {
+        // Spread methods measure divergence, so disagreement is the signal rather than an error
+        let is_spread = matches!(data_req.aggregation_method, AggregationMethod::MaxMinusMin);
+
+        // Check price deviation for numeric values (skipped when the request opts out)
+        let deviation_epsilon = config.deviation_epsilon.unwrap_or(DEFAULT_DEVIATION_EPSILON);
+        let deviation = if data_req.check_deviation {
+            aggregation::calculate_price_deviation(&source_prices, deviation_epsilon, data_req.use_decimal)
+        } else {
+            0.0
+        };
+        if !is_spread && deviation > max_deviation {
             let error_msg = format!(
                 "Price deviation too high: {:.2}% (max: {:.2}%)",
                 deviation, max_deviation
             );
 
-            return DataResponse {
-                id: data_req.id.clone(),
-                data: None,
-                message: Some(error_msg),
-            };
+            match data_req.on_deviation {
+                OnDeviation::Fail => return DataResponse::failed(&data_req.id, error_msg),
+                OnDeviation::UseMedian => {
+                    // Median is the robust estimate when sources disagree
+                    aggregation_method = AggregationMethod::Median;
+                    warnings.push(format!("{}, policy use_median applied", error_msg));
+                }
+                OnDeviation::DropOutliersThenRetry => {
+                    // Drop the source farthest from the median and re-check once
+                    if let Some(index) = aggregation::farthest_from_median(&source_prices) {
+                        let outlier = source_prices.remove(index);
+                        let retry_deviation = aggregation::calculate_price_deviation(&source_prices, deviation_epsilon, data_req.use_decimal);
+
+                        if retry_deviation > max_deviation || source_prices.len() < data_req.min_sources_num {
+                            let error_msg = format!(
+                                "Price deviation too high: {:.2}% after dropping outlier {} (max: {:.2}%)",
+                                retry_deviation, outlier.source_name, max_deviation
+                            );
+                            return DataResponse::failed(&data_req.id, error_msg);
+                        }
+
+                        warnings.push(format!(
+                            "{}, policy drop_outliers_then_retry dropped {}",
+                            error_msg, outlier.source_name
+                        ));
+                        dropped.push(DroppedSource::new(
+                            &outlier.source_name,
+                            format!("outlier farthest from median ({:.2}% deviation)", deviation),
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Identical values from independent sources hint at a shared upstream/cache
+        if !is_spread && data_req.detect_identical && aggregation::all_values_identical(&source_prices) {
+            let warning = format!(
+                "Suspicious: all {} sources returned an identical value",
+                source_prices.len()
+            );
+
+            if data_req.suspicious_identical_is_error {
+                return DataResponse::failed(&data_req.id, warning);
+            }
+            warnings.push(warning);
         }
 
         // Aggregate numeric values
-        match aggregation::aggregate_prices(&source_prices, &data_req.aggregation_method) {
-            Ok(price) => types::DataValue::Number(price),
+        let mut aggregate = match aggregation::aggregate_prices(&source_prices, &aggregation_method, config.deterministic, data_req.use_decimal) {
+            Ok(price) => price,
             Err(e) => {
-                return DataResponse {
-                    id: data_req.id.clone(),
-                    data: None,
-                    message: Some(format!("Aggregation failed: {}", e)),
+                return DataResponse::failed(&data_req.id, format!("Aggregation failed: {}", e));
+            }
+        };
+
+        // Strict deviation: every source must also be close to the aggregate itself
+        if !is_spread && data_req.strict_deviation != StrictDeviation::Off {
+            let limit = data_req.strict_deviation_percent.unwrap_or(max_deviation);
+            let offenders: Vec<(String, f64)> = source_prices.iter()
+                .filter_map(|p| p.value.as_number().map(|n| (p, aggregation::deviation_from(n, aggregate))))
+                .filter(|(_, deviation)| *deviation > limit)
+                .map(|(p, deviation)| (p.source_name.clone(), deviation))
+                .collect();
+
+            if !offenders.is_empty() {
+                let listed: Vec<String> = offenders.iter()
+                    .map(|(name, deviation)| format!("{} {:.2}%", name, deviation))
+                    .collect();
+
+                if data_req.strict_deviation == StrictDeviation::Fail {
+                    let error_msg = format!(
+                        "Sources deviate from the aggregate by more than {:.2}%: {}",
+                        limit,
+                        listed.join(", ")
+                    );
+                    return DataResponse::failed(&data_req.id, error_msg);
+                }
+
+                // Drop the offenders and aggregate once more over the conforming sources
+                source_prices.retain(|p| {
+                    p.value.as_number().is_none_or(|n| aggregation::deviation_from(n, aggregate) <= limit)
+                });
+                if source_prices.len() < data_req.min_sources_num {
+                    let error_msg = format!(
+                        "Not enough sources within {:.2}% of the aggregate ({}/{}): dropped {}",
+                        limit,
+                        source_prices.len(),
+                        data_req.min_sources_num,
+                        listed.join(", ")
+                    );
+                    return DataResponse::failed(&data_req.id, error_msg);
+                }
+                for (name, deviation) in &offenders {
+                    dropped.push(DroppedSource::new(name, format!("{:.2}% from aggregate (max {:.2}%)", deviation, limit)));
+                }
+                warnings.push(format!("Strict deviation dropped {}", listed.join(", ")));
+
+                aggregate = match aggregation::aggregate_prices(&source_prices, &aggregation_method, config.deterministic, data_req.use_decimal) {
+                    Ok(price) => price,
+                    Err(e) => {
+                        return DataResponse::failed(&data_req.id, format!("Aggregation failed: {}", e));
+                    }
                 };
             }
         }
+
+        // With an even count the median is the midpoint of the two middle values, which can sit
+        // between two disagreeing clusters that neither source supports
+        if data_req.check_deviation && matches!(aggregation_method, AggregationMethod::Median) {
+            if let Some((low, high)) = aggregation::median_middle_pair(&source_prices) {
+                let gap = aggregation::deviation_from(high, low);
+                if gap > max_deviation {
+                    let mut warning = format!(
+                        "Median ambiguity: middle values {} and {} differ by {:.2}% (max: {:.2}%)",
+                        low, high, gap, max_deviation
+                    );
+                    if let Some(fallback) = &data_req.median_ambiguity_fallback {
+                        aggregate = match aggregation::aggregate_prices(&source_prices, fallback, config.deterministic, data_req.use_decimal) {
+                            Ok(price) => price,
+                            Err(e) => {
+                                return DataResponse::failed(&data_req.id, format!("Aggregation failed: {}", e));
+                            }
+                        };
+                        aggregation_method = fallback.clone();
+                        warning = format!(
+                            "{}, fell back to {}",
+                            warning,
+                            serde_json::to_string(fallback).unwrap_or_default().trim_matches('"')
+                        );
+                    }
+                    warnings.push(warning);
+                    degraded = true;
+                }
+            }
+        }
+
+        types::DataValue::Number(aggregate)
     } else {
         // No numeric values - return first value as-is (text or boolean)
         source_prices[0].value.clone()
     };
 
+    // NaN/Infinity would serialize as null; fail explicitly instead
+    if let Err(error_msg) = validation::check_finite(&final_value) {
+        return DataResponse::failed(&data_req.id, error_msg);
+    }
+
+    // Canaries watch the aggregate in the sources' own units, before any conversion
+    if let Some(aggregate) = final_value.as_number() {
+        let limit = data_req.canary_max_deviation_percent.unwrap_or(max_deviation);
+        for canary in canary_prices.iter_mut() {
+            let Some(value) = canary.value.as_number() else {
+                warnings.push(format!("Canary {} returned a non-numeric value", canary.source_name));
+                continue;
+            };
+            let deviation = aggregation::deviation_from(value, aggregate);
+            canary.detail.canary_deviation_percent = Some(deviation);
+
+            if deviation > limit {
+                let warning = format!(
+                    "Canary {} disagrees with the aggregate by {:.2}% (max: {:.2}%)",
+                    canary.source_name, deviation, limit
+                );
+                if data_req.canary_is_error {
+                    return DataResponse::failed(&data_req.id, warning);
+                }
+                warnings.push(warning);
+            }
+        }
+    }
+    for error in &canary_errors {
+        warnings.push(format!("Canary unavailable: {}", error));
+    }
+
+    // Cross-rate conversion with the shared rate resolved once for the whole batch
+    if let Some(rate_id) = &data_req.convert_rate {
+        let rate = match run.shared_rates.get(rate_id) {
+            Some(Ok(rate)) => *rate,
+            Some(Err(e)) => {
+                return DataResponse::failed(&data_req.id, format!("Shared rate '{}' unavailable: {}", rate_id, e));
+            }
+            None => return DataResponse::failed(&data_req.id, format!("Unknown shared rate '{}'", rate_id)),
+        };
+        match final_value {
+            types::DataValue::Number(value) => final_value = types::DataValue::Number(value * rate),
+            _ => return DataResponse::failed(&data_req.id, "convert_rate requires a numeric value".to_string()),
+        }
+    }
+
+    // Clamp transient spikes to a band around the caller's reference price instead of failing
+    if let (Some(reference), Some(max_change), types::DataValue::Number(value)) =
+        (data_req.reference_price, data_req.max_change_percent_from_reference, &final_value)
+    {
+        let band = reference.abs() * max_change / 100.0;
+        let clamped = value.clamp(reference - band, reference + band);
+        if clamped != *value {
+            warnings.push(format!(
+                "Clamped {} to {} ({}% from reference {})",
+                value, clamped, max_change, reference
+            ));
+            final_value = types::DataValue::Number(clamped);
+            degraded = true;
+        }
+    }
+
+    // Bounds on the final value (e.g. alert when node heights diverge too far)
+    if let Some(value) = final_value.as_number() {
+        if let Some(min) = data_req.min_value.filter(|&min| value < min) {
+            return DataResponse::failed(&data_req.id, format!("Value {} is below min_value {}", value, min));
+        }
+        if let Some(max) = data_req.max_value.filter(|&max| value > max) {
+            return DataResponse::failed(&data_req.id, format!("Value {} is above max_value {}", value, max));
+        }
+    }
+
     // Build detailed message with source prices for numeric aggregation
-    let detailed_message = if has_numeric && source_prices.len() > 1 {
+    let detailed_message = if has_numeric && vote_info.is_none() && source_prices.len() > 1 {
         let source_details: Vec<String> = source_prices.iter()
             .filter_map(|p| {
                 p.value.as_number().map(|n| format!("{}: {:.6}", p.source_name, n))
             })
             .collect();
 
-        let aggregation_label = match data_req.aggregation_method {
+        let aggregation_label = match aggregation_method {
             types::AggregationMethod::Average => "avg",
             types::AggregationMethod::Median => "median",
+            types::AggregationMethod::Latest => "latest",
             types::AggregationMethod::WeightedAvg => "weighted",
+            types::AggregationMethod::FreshnessWeighted => "freshness",
+            types::AggregationMethod::ReputationWeighted => "reputation",
+            types::AggregationMethod::InverseVarianceWeighted => "inverse_variance",
+            types::AggregationMethod::RobustIrls => "robust_irls",
+            types::AggregationMethod::MaxMinusMin => "max_minus_min",
+            types::AggregationMethod::BoolAnd => "and",
+            types::AggregationMethod::BoolOr => "or",
+            types::AggregationMethod::BoolMajority => "majority",
         };
 
         if let types::DataValue::Number(final_price) = final_value {
             let details = source_details.join(", ");
-            let agg_info = format!("{}, {}: {:.6}", details, aggregation_label, final_price);
+            let mut agg_info = format!("{}, {}: {:.6}", details, aggregation_label, final_price);
+
+            // Show the computed weights so the result can be reproduced
+            let shown_weights = match aggregation_method {
+                types::AggregationMethod::FreshnessWeighted => Some(aggregation::freshness_weights(&source_prices)),
+                types::AggregationMethod::ReputationWeighted => Some(aggregation::reputation_weights(&source_prices)),
+                types::AggregationMethod::RobustIrls => Some(aggregation::robust_irls_weights(&source_prices)),
+                _ => None,
+            };
+            if let Some(shown_weights) = shown_weights {
+                let weights: Vec<String> = source_prices.iter()
+                    .zip(shown_weights)
+                    .filter(|(p, _)| p.value.as_number().is_some())
+                    .map(|(p, w)| format!("{}: {:.4}", p.source_name, w))
+                    .collect();
+                agg_info = format!("{}, weights: [{}]", agg_info, weights.join(", "));
+            }
 
             // Add error info if any sources failed
             if !errors.is_empty() {
@@ -186,17 +1289,323 @@ fn process_data_request(
         } else {
             message
         }
+    } else if let Some(votes) = vote_info {
+        // Report the vote tally for boolean aggregation
+        if !errors.is_empty() {
+            Some(format!("{}. Errors: {}", votes, errors.join(", ")))
+        } else {
+            Some(votes)
+        }
     } else {
         message
     };
 
+    // Use the latest timestamp from all sources
+    let latest_timestamp = source_prices.iter().map(|p| p.timestamp).max().unwrap_or(0);
+
+    // Collect source names
+    let source_names: Vec<String> = source_prices.iter().map(|p| p.source_name.clone()).collect();
+
+    // Combined confidence for inverse-variance weighting
+    let confidence = match aggregation_method {
+        AggregationMethod::InverseVarianceWeighted if has_numeric => {
+            aggregation::combined_confidence(&source_prices)
+        }
+        _ => None,
+    };
+
+    // Fee/slippage-adjusted settlement price next to the raw aggregate
+    let adjusted_value = match (data_req.fee_bps, final_value.as_number()) {
+        (Some(bps), Some(value)) => {
+            let fee = bps / 10_000.0;
+            let adjusted = match data_req.fee_side {
+                FeeSide::Buy => value * (1.0 + fee),
+                FeeSide::Sell => value * (1.0 - fee),
+            };
+            Some(DataValue::Number(if config.deterministic {
+                aggregation::round_deterministic(adjusted)
+            } else {
+                adjusted
+            }))
+        }
+        _ => None,
+    };
+
+    // Attribution when the result is one source's value rather than a blend
+    let selected_source = aggregation::selected_source(&source_prices, &aggregation_method, &final_value)
+        .map(str::to_string);
+
+    // Price band around the aggregate (spread methods already are the spread)
+    let spread_percent = match (&final_value, &aggregation_method) {
+        (_, AggregationMethod::MaxMinusMin) => None,
+        (DataValue::Number(_), _) => aggregation::spread_over_median(&source_prices),
+        _ => None,
+    };
+
+    // Stablecoin health: the price plus a `depegged` flag as named outputs
+    let outputs = match (data_req.depeg_threshold_percent, &final_value) {
+        (Some(threshold), DataValue::Number(price)) => {
+            let peg = data_req.peg.unwrap_or(1.0);
+            let off_peg = aggregation::deviation_from(*price, peg);
+            let depegged = off_peg > threshold;
+            if depegged {
+                warnings.push(format!("Depegged: {:.2}% from peg {} (threshold {:.2}%)", off_peg, peg, threshold));
+            }
+
+            let output = |name: &str, value: DataValue| NamedValue {
+                name: name.to_string(),
+                value: Some(value),
+                sources: source_names.clone(),
+                message: None,
+            };
+            Some(vec![
+                output("price", final_value.clone()),
+                output("depegged", DataValue::Boolean(depegged)),
+            ])
+        }
+        _ => None,
+    };
+
+    // Per-source diagnostics, only when an execution option asks for them
+    let sources_detail = if config.wants_sources_detail() {
+        let irls_weights = matches!(aggregation_method, AggregationMethod::RobustIrls)
+            .then(|| aggregation::robust_irls_weights(&source_prices));
+        Some(source_prices.iter().enumerate().map(|(i, p)| {
+            let mut detail = p.detail.clone();
+            detail.timestamp = Some(p.timestamp);
+            detail.weight = irls_weights.as_ref().map(|weights| weights[i]);
+            if config.always_include_sources {
+                detail.value = Some(p.value.clone());
+            }
+            detail
+        }).chain(canary_prices.iter().map(|p| {
+            let mut detail = p.detail.clone();
+            detail.timestamp = Some(p.timestamp);
+            if config.always_include_sources {
+                detail.value = Some(p.value.clone());
+            }
+            detail
+        })).collect())
+    } else {
+        None
+    };
+
+    // Commitment to every contribution, so one can be challenged later with a Merkle proof
+    let contributions_merkle_root = if config.merkle_contributions {
+        merkle::contributions_root(&source_prices)
+    } else {
+        None
+    };
+
+    // How many contributing values carried a valid signature
+    let verified_sources = if data_req.sources.iter().any(|s| s.verify_signature.is_some()) {
+        Some(source_prices.iter().filter(|p| p.detail.signed_by.is_some()).count())
+    } else {
+        None
+    };
+
+    // Append warnings to whatever message we already have
+    let detailed_message = if warnings.is_empty() {
+        detailed_message
+    } else {
+        let warning_info = format!("Warnings: {}", warnings.join(", "));
+        match detailed_message {
+            Some(msg) => Some(format!("{}. {}", msg, warning_info)),
+            None => Some(warning_info),
+        }
+    };
+
     DataResponse {
         id: data_req.id.clone(),
         data: Some(PriceData {
             value: final_value,
             timestamp: latest_timestamp,
+            timestamp_iso: None,
             sources: source_names,
+            confidence,
+            adjusted_value,
+            spread_percent,
+            selected_source,
+            sources_detail,
+            contributions_merkle_root,
+            verified_sources,
+            consensus_sources,
+            excluded_sources,
+            dropped,
+            degraded,
+            outputs,
         }),
         message: detailed_message,
+        history: None,
+        probe: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pretty_and_compact_output_hold_the_same_value() {
+        let response: OracleResponse = serde_json::from_value(json!({
+            "results": [
+                {
+                    "id": "bitcoin",
+                    "data": {"value": 100000.5, "timestamp": 1700000000, "sources": ["coingecko", "binance"]},
+                    "message": null
+                },
+                {"id": "pepe", "data": null, "message": "Not enough sources"}
+            ]
+        })).unwrap();
+
+        let compact = render_response(&response, false).unwrap();
+        let pretty = render_response(&response, true).unwrap();
+        assert_ne!(compact, pretty);
+        assert!(pretty.contains('\n'));
+
+        let reparsed: OracleResponse = serde_json::from_str(&pretty).unwrap();
+        assert_eq!(render_response(&reparsed, false).unwrap(), compact);
+    }
+
+    #[test]
+    fn monotonic_regression_fails_or_degrades() {
+        let request = |action: &str| -> DataRequest {
+            serde_json::from_value(json!({
+                "id": "height",
+                "sources": [{"name": "custom"}],
+                "monotonic": true,
+                "monotonic_action": action
+            })).unwrap()
+        };
+        let response = || -> DataResponse {
+            serde_json::from_value(json!({
+                "id": "height",
+                "data": {"value": 99.0, "timestamp": 1700000000, "sources": ["custom"]},
+                "message": null
+            })).unwrap()
+        };
+
+        let rejected = apply_monotonic_guard(response(), &request("reject"), Some(100.0));
+        assert!(rejected.data.is_none());
+        assert!(rejected.message.unwrap().contains("Non-monotonic value: 99 is below the last stored 100"));
+
+        let degraded = apply_monotonic_guard(response(), &request("degrade"), Some(100.0));
+        assert!(degraded.data.as_ref().unwrap().degraded);
+        assert!(degraded.message.unwrap().contains("Non-monotonic value"));
+
+        // The degraded value is not stored, so the next run still compares against 100
+        let path = env::temp_dir().join(format!("oracle-monotonic-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let stored: DataResponse = serde_json::from_value(json!({
+            "id": "height",
+            "data": {"value": 100.0, "timestamp": 1699999990, "sources": ["custom"]},
+            "message": null
+        })).unwrap();
+        record_history(path, &[stored]).unwrap();
+        record_history(path, &[apply_monotonic_guard(response(), &request("degrade"), Some(100.0))]).unwrap();
+        let last = state::OracleState::load(path).unwrap().last_samples("height", 1).first().map(|sample| sample.value);
+        let _ = std::fs::remove_file(path);
+        assert_eq!(last, Some(100.0));
+        assert!(apply_monotonic_guard(response(), &request("degrade"), last).data.unwrap().degraded);
+
+        let passed = apply_monotonic_guard(response(), &request("reject"), Some(99.0));
+        assert!(!passed.data.unwrap().degraded);
+        assert!(apply_monotonic_guard(response(), &request("reject"), None).data.is_some());
+    }
+
+    #[test]
+    fn fresh_cache_entries_stay_out_of_the_batch_prefetch() {
+        let data_req: DataRequest = serde_json::from_value(json!({
+            "id": "bitcoin",
+            "sources": [{"name": "coingecko"}, {"name": "pyth", "id": "0xabc"}]
+        })).unwrap();
+        let config = ExecutionConfig::default();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let mut cache = SourceCache::default();
+        let cached = &data_req.sources[0];
+        let price = types::SourcePrice::new("coingecko".to_string(), DataValue::Number(100.0), now);
+        cache.insert(SourceCache::key("coingecko", cached, "bitcoin"), &price, now);
+
+        let prefetch = without_cached_sources(&data_req, Some(&cache), &config);
+        let names: Vec<&str> = prefetch.sources.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["pyth"]);
+        assert_eq!(without_cached_sources(&data_req, None, &config).sources.len(), 2);
+
+        // An expired entry is refetched, so the source is batched again
+        let mut stale = SourceCache::default();
+        stale.insert(SourceCache::key("coingecko", cached, "bitcoin"), &price, now - DEFAULT_CACHE_TTL_SECS - 1);
+        assert_eq!(without_cached_sources(&data_req, Some(&stale), &config).sources.len(), 2);
+    }
+
+    #[test]
+    fn denied_source_is_not_served_from_the_cache() {
+        let config: ExecutionConfig = serde_json::from_value(json!({"denied_sources": ["coingecko"]})).unwrap();
+        let data_req: DataRequest = serde_json::from_value(json!({
+            "id": "bitcoin",
+            "sources": [{"name": "coingecko"}]
+        })).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let mut cache = SourceCache::default();
+        let price = types::SourcePrice::new("coingecko".to_string(), DataValue::Number(100.0), now);
+        cache.insert(SourceCache::key("coingecko", &data_req.sources[0], "bitcoin"), &price, now);
+
+        let no_keys = || KeyRing { keys: Vec::new(), next: Cell::new(0) };
+        let api_keys = ApiKeys { coingecko: no_keys(), coinmarketcap: no_keys(), twelvedata: no_keys(), messari: no_keys() };
+        let batch = BatchResults::new();
+        let run = Invocation {
+            max_deviation: 5.0,
+            batch: &batch,
+            config: &config,
+            api_keys: &api_keys,
+            deadline: None,
+            usdt_usd_rate: None,
+            shared_rates: HashMap::new(),
+        };
+
+        let response = process_data_request(&data_req, &run, Some(&mut cache));
+        assert!(response.data.is_none());
+        assert!(response.message.unwrap().contains("not allowed by this deployment"));
+    }
+
+    #[test]
+    fn failed_canaries_stay_out_of_the_request_errors() {
+        let config: ExecutionConfig = serde_json::from_value(json!({"denied_sources": ["binance"]})).unwrap();
+        let data_req: DataRequest = serde_json::from_value(json!({
+            "id": "bitcoin",
+            "sources": [
+                {"name": "coingecko"},
+                {"name": "binance", "canary": true},
+                {"name": "huobi", "canary": true}
+            ]
+        })).unwrap();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        let mut cache = SourceCache::default();
+        let price = types::SourcePrice::new("coingecko".to_string(), DataValue::Number(100.0), now);
+        cache.insert(SourceCache::key("coingecko", &data_req.sources[0], "bitcoin"), &price, now);
+
+        let no_keys = || KeyRing { keys: Vec::new(), next: Cell::new(0) };
+        let api_keys = ApiKeys { coingecko: no_keys(), coinmarketcap: no_keys(), twelvedata: no_keys(), messari: no_keys() };
+        let batch = BatchResults::new();
+        // The deadline has already passed, so the huobi canary is skipped (fail_all is the default)
+        let run = Invocation {
+            max_deviation: 5.0,
+            batch: &batch,
+            config: &config,
+            api_keys: &api_keys,
+            deadline: Some(Instant::now()),
+            usdt_usd_rate: None,
+            shared_rates: HashMap::new(),
+        };
+
+        let response = process_data_request(&data_req, &run, Some(&mut cache));
+        assert!(response.data.is_some());
+        let message = response.message.unwrap();
+        assert_eq!(message.matches("binance:").count(), 1);
+        assert!(message.contains("Canary unavailable: binance:"));
+        assert!(message.contains("Canary unavailable: huobi: deadline exceeded"));
     }
 }
@@ -0,0 +1,122 @@
+use crate::types::{DataValue, SourcePrice};
+use sha2::{Digest, Sha256};
+
+// Domain separation prefixes so a leaf can never be passed off as an inner node
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Canonical bytes of one contribution:
+/// u32 BE name length, name (UTF-8), value, u64 BE timestamp
+/// Value: 0x01 + f64 BE bits (number), 0x02 + u32 BE length + UTF-8 (text), 0x03 + 0x00/0x01 (boolean)
+fn leaf_bytes(price: &SourcePrice) -> Vec<u8> {
+    let name = price.source_name.as_bytes();
+    let mut bytes = Vec::with_capacity(name.len() + 32);
+    bytes.extend_from_slice(&(name.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(name);
+
+    match &price.value {
+        DataValue::Number(n) => {
+            bytes.push(0x01);
+            bytes.extend_from_slice(&n.to_bits().to_be_bytes());
+        }
+        DataValue::Text(text) => {
+            bytes.push(0x02);
+            bytes.extend_from_slice(&(text.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        DataValue::Boolean(b) => {
+            bytes.push(0x03);
+            bytes.push(*b as u8);
+        }
+    }
+
+    bytes.extend_from_slice(&price.timestamp.to_be_bytes());
+    bytes
+}
+
+/// SHA-256(0x00 || leaf_bytes)
+fn leaf_hash(price: &SourcePrice) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(leaf_bytes(price));
+    hasher.finalize().into()
+}
+
+/// SHA-256(0x01 || left || right)
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Hex Merkle root over the contributions, leaves sorted by source name (bytes), then leaf hash
+/// An odd node at the end of a level is carried up unchanged; None when there are no contributions
+pub fn contributions_root(prices: &[SourcePrice]) -> Option<String> {
+    let mut leaves: Vec<(&str, [u8; 32])> = prices.iter()
+        .map(|p| (p.source_name.as_str(), leaf_hash(p)))
+        .collect();
+    leaves.sort();
+
+    let mut level: Vec<[u8; 32]> = leaves.into_iter().map(|(_, hash)| hash).collect();
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+
+    level.first().map(hex::encode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(name: &str, value: DataValue, timestamp: u64) -> SourcePrice {
+        SourcePrice::new(name.to_string(), value, timestamp)
+    }
+
+    fn contributions() -> Vec<SourcePrice> {
+        vec![
+            price("binance", DataValue::Number(100.0), 1700000000),
+            price("coingecko", DataValue::Number(100.5), 1700000001),
+            price("kraken", DataValue::Text("open".to_string()), 1700000002),
+        ]
+    }
+
+    // Expected roots were computed independently from the documented encoding
+    #[test]
+    fn roots_match_known_answers() {
+        let prices = contributions();
+        assert_eq!(contributions_root(&[]), None);
+        assert_eq!(
+            contributions_root(&prices[..1]).as_deref(),
+            Some("a5695a07da3c098c0327659b95b4dfe7161a257b3b86492f4ddd2aede89e9738")
+        );
+        assert_eq!(
+            contributions_root(&prices[..2]).as_deref(),
+            Some("b345aa74c09d914864007bc89d97f949297deef4ea5462e39c7f4ea58e0e5374")
+        );
+        // The third leaf has no sibling and is carried up to pair with the first node
+        assert_eq!(
+            contributions_root(&prices).as_deref(),
+            Some("2467909ff71eb297798760a266b14cdd8bdc0117ef54a9a7d8a12bf00d9ba025")
+        );
+    }
+
+    #[test]
+    fn source_order_does_not_change_the_root() {
+        let prices = contributions();
+        let mut reversed = prices.clone();
+        reversed.reverse();
+        let rotated = vec![prices[1].clone(), prices[2].clone(), prices[0].clone()];
+
+        assert_eq!(contributions_root(&prices), contributions_root(&reversed));
+        assert_eq!(contributions_root(&prices), contributions_root(&rotated));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::types::OracleResponse;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use std::env;
+use std::error::Error;
+
+// Private key used to sign responses for on-chain verification
+pub const ED25519_KEY_VAR: &str = "ORACLE_ED25519_KEY";
+
+/// Load the signing key from ORACLE_ED25519_KEY (None when unset)
+/// Accepts a 32-byte seed or a 64-byte keypair (seed + public key), as hex or base58,
+/// optionally prefixed with "ed25519:" as in NEAR key files
+pub fn signing_key_from_env() -> Result<Option<SigningKey>, Box<dyn Error>> {
+    let raw = match env::var(ED25519_KEY_VAR) {
+        Ok(raw) => raw,
+        Err(_) => return Ok(None),
+    };
+
+    let encoded = raw.trim();
+    let encoded = encoded.strip_prefix("ed25519:").unwrap_or(encoded);
+
+    let bytes = match hex::decode(encoded) {
+        Ok(bytes) => bytes,
+        Err(_) => bs58::decode(encoded)
+            .into_vec()
+            .map_err(|_| format!("{} is neither hex nor base58", ED25519_KEY_VAR))?,
+    };
+
+    let seed: [u8; 32] = match bytes.len() {
+        32 | 64 => bytes[..32].try_into()?,
+        n => return Err(format!("{} must be 32 or 64 bytes, got {}", ED25519_KEY_VAR, n).into()),
+    };
+
+    Ok(Some(SigningKey::from_bytes(&seed)))
+}
+
+/// Sign the compact JSON of the response as it looks without the signature fields
+/// Those fields serialize last, so the signed bytes are the output up to `,"ed25519_signature"` plus `}`
+pub fn sign_response(response: &mut OracleResponse, key: &SigningKey) -> Result<(), Box<dyn Error>> {
+    response.ed25519_signature = None;
+    response.ed25519_pubkey = None;
+
+    let message = serde_json::to_vec(response)?;
+    let signature = key.sign(&message);
+
+    response.ed25519_signature = Some(hex::encode(signature.to_bytes()));
+    response.ed25519_pubkey = Some(format!(
+        "ed25519:{}",
+        bs58::encode(key.verifying_key().to_bytes()).into_string()
+    ));
+    Ok(())
+}
+
+/// Verify a source's signature over `message` and return the signer if it is in `allowed_pubkeys`
+pub fn verify_source_signature(
+    message: &[u8],
+    signature: &str,
+    pubkey: &str,
+    allowed_pubkeys: &[String],
+) -> Result<String, String> {
+    let signer = decode_pubkey(pubkey).ok_or_else(|| format!("unreadable public key '{}'", pubkey))?;
+    let allowed = allowed_pubkeys.iter().any(|key| decode_pubkey(key).is_some_and(|key| key == signer));
+    if !allowed {
+        return Err(format!("signer '{}' is not in allowed_pubkeys", pubkey));
+    }
+
+    let signature = decode_bytes(signature)
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or("signature must be 64 bytes, hex or base64")?;
+    let key = VerifyingKey::from_bytes(&signer).map_err(|_| format!("invalid public key '{}'", pubkey))?;
+
+    key.verify(message, &signature).map_err(|_| "invalid signature".to_string())?;
+    Ok(pubkey.to_string())
+}
+
+/// 32-byte ed25519 public key from hex, "ed25519:<base58>" (NEAR), base58 or base64
+fn decode_pubkey(encoded: &str) -> Option<[u8; 32]> {
+    let encoded = encoded.trim();
+    let bytes = match encoded.strip_prefix("ed25519:") {
+        Some(base58) => bs58::decode(base58).into_vec().ok()?,
+        None => decode_bytes(encoded)
+            .filter(|bytes| bytes.len() == 32)
+            .or_else(|| bs58::decode(encoded).into_vec().ok())?,
+    };
+    bytes.try_into().ok()
+}
+
+/// Hex first, then standard base64
+fn decode_bytes(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.trim();
+    hex::decode(encoded)
+        .ok()
+        .or_else(|| base64::engine::general_purpose::STANDARD.decode(encoded).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 8032 section 7.1, test 1
+    const SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const PUBKEY: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&hex::decode(SEED).unwrap().try_into().unwrap())
+    }
+
+    #[test]
+    fn response_signature_covers_the_compact_json_without_signature_fields() {
+        let mut response: OracleResponse = serde_json::from_value(serde_json::json!({
+            "results": [{"id": "bitcoin", "data": {"value": 100000.5, "timestamp": 1700000000, "sources": ["coingecko"]}, "message": null}]
+        })).unwrap();
+        let signed_bytes = r#"{"results":[{"id":"bitcoin","data":{"value":100000.5,"timestamp":1700000000,"sources":["coingecko"]},"message":null}]}"#;
+
+        sign_response(&mut response, &signing_key()).unwrap();
+
+        let pubkey = format!("ed25519:{}", bs58::encode(hex::decode(PUBKEY).unwrap()).into_string());
+        assert_eq!(response.ed25519_pubkey.as_deref(), Some(pubkey.as_str()));
+        let signature = response.ed25519_signature.clone().unwrap();
+        assert_eq!(signature, "19b07edacf89055cf57a85d67f565449620e95ac9a3f160eac7115d5d2fb0905c7b77d30658b7d9bbc3885eac731a7f5ca60c49bc2b5e834a9b6725ed8821501");
+
+        // The output is the signed bytes with the signature fields appended
+        let output = serde_json::to_string(&response).unwrap();
+        assert!(output.starts_with(&signed_bytes[..signed_bytes.len() - 1]));
+        assert!(verify_source_signature(signed_bytes.as_bytes(), &signature, &pubkey, &[PUBKEY.to_string()]).is_ok());
+    }
+
+    #[test]
+    fn source_signatures_round_trip_and_check_the_signer() {
+        // RFC 8032 test 1 signs the empty message
+        let rfc_signature = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+        assert_eq!(hex::encode(signing_key().sign(b"").to_bytes()), rfc_signature);
+        assert_eq!(verify_source_signature(b"", rfc_signature, PUBKEY, &[PUBKEY.to_string()]), Ok(PUBKEY.to_string()));
+
+        let message = br#"{"price":"3120.55","ts":1700000000}"#;
+        let signature = base64::engine::general_purpose::STANDARD.encode(signing_key().sign(message).to_bytes());
+        let near_key = format!("ed25519:{}", bs58::encode(hex::decode(PUBKEY).unwrap()).into_string());
+        assert!(verify_source_signature(message, &signature, &near_key, &[PUBKEY.to_string()]).is_ok());
+
+        assert_eq!(
+            verify_source_signature(b"tampered", &signature, &near_key, std::slice::from_ref(&near_key)),
+            Err("invalid signature".to_string())
+        );
+        let other = hex::encode(SigningKey::from_bytes(&[7; 32]).verifying_key().to_bytes());
+        assert!(verify_source_signature(message, &signature, &near_key, &[other]).unwrap_err().contains("not in allowed_pubkeys"));
+    }
+}
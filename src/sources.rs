@@ -1,46 +1,410 @@
-use crate::types::{SourcePrice, CustomSourceConfig, ValueType, DataValue};
-use serde_json::Value;
+use crate::types::{SourcePrice, CustomSourceConfig, DepthConfig, Paginate, PageReduce, SeriesMethod, SeriesReduce, ValueType, DataValue, DataRequest, NearViewConfig, ExecutionConfig, SourceDetail, PriceSource, IdKind, PriceKind, SigConfig, DEFAULT_IPFS_GATEWAY, DEFAULT_RETRY_BACKOFF_MS, MAX_RAW_CAPTURE_BYTES};
+use crate::logging;
+use crate::signing;
+use crate::timefmt;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::error::Error;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::env;
-use wasi_http_client::Client;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::OnceLock;
+use std::thread;
+use wasi_http_client::{Client, Response};
+
+/// Shared state for a single source fetch: execution settings plus collected diagnostics
+pub struct FetchContext<'a> {
+    pub config: &'a ExecutionConfig,
+    pub source_name: &'a str,
+    pub detail: SourceDetail,
+    /// Replace invalid UTF-8 in response bodies instead of failing (custom text sources)
+    pub lossy_decode: bool,
+    /// Total response body bytes received so far (all calls of this fetch)
+    pub bytes_received: usize,
+    /// Overall deadline (total_deadline_secs); 429 retries never sleep past it
+    pub deadline: Option<Instant>,
+}
+
+impl<'a> FetchContext<'a> {
+    pub fn new(config: &'a ExecutionConfig, source_name: &'a str) -> Self {
+        FetchContext {
+            config,
+            source_name,
+            detail: SourceDetail::default(),
+            lossy_decode: false,
+            bytes_received: 0,
+            deadline: None,
+        }
+    }
+
+    /// Apply `base_url_overrides` for this source: keep path and query, swap scheme/host
+    /// e.g. "https://api.binance.com/api/v3/ticker/price?symbol=X" with override
+    /// "http://localhost:8080" -> "http://localhost:8080/api/v3/ticker/price?symbol=X"
+    fn resolve_url(&self, url: &str) -> String {
+        let base = match self.config.base_url_overrides.get(self.source_name) {
+            Some(base) if self.source_name != "custom" => base,
+            _ => return url.to_string(),
+        };
+
+        let path_and_query = url.split_once("://")
+            .and_then(|(_, rest)| rest.find('/').map(|i| &rest[i..]))
+            .unwrap_or("");
+
+        format!("{}{}", base.trim_end_matches('/'), path_and_query)
+    }
+
+    /// Send HTTP GET request and parse the JSON response
+    fn get_json(&mut self, url: &str, headers: &[(&str, &str)]) -> Result<Value, Box<dyn Error>> {
+        self.send_json("GET", url, headers, None)
+    }
+
+    /// Send HTTP request, check status and parse the JSON response
+    fn send_json(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<Value, Box<dyn Error>> {
+        let (status, json) = self.send_json_any_status(method, url, headers, body)?;
+        if status < 200 || status >= 300 {
+            return Err(format!("HTTP {}", status).into());
+        }
+
+        Ok(json)
+    }
+
+    /// Send HTTP request and parse the JSON response regardless of status
+    /// For APIs that put a useful error message in non-2xx bodies
+    /// All fetchers go through here so diagnostics are collected in one place
+    fn send_json_any_status(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<(u16, Value), Box<dyn Error>> {
+        let mut url = self.resolve_url(url);
+        let mut method = method;
+        let mut body = body;
+        let mut headers = headers.to_vec();
+        let mut visited: Vec<String> = Vec::new();
+
+        // Follow 3xx responses (unless disabled) with loop, hop and downgrade guards
+        let response = loop {
+            let response = self.send_with_fallbacks(method, &url, &headers, body)?;
+            let status = response.status();
+            if !matches!(status, 301 | 302 | 303 | 307 | 308) {
+                break response;
+            }
+
+            // No Location: nothing to follow, reported as a plain HTTP error below
+            let Some(location) = header_value(&response, "location") else {
+                break response;
+            };
+            let next = resolve_against(&url, location)
+                .ok_or_else(|| format!("HTTP {} with unusable Location '{}'", status, logging::redact(location)))?;
+
+            if !self.config.follow_redirects.unwrap_or(true) {
+                return Err(format!("HTTP {} redirect to {}", status, logging::redact(&next)).into());
+            }
+            if visited.len() >= MAX_REDIRECTS {
+                return Err(format!("Too many redirects (more than {})", MAX_REDIRECTS).into());
+            }
+            if next == url || visited.contains(&next) {
+                return Err(format!("Redirect loop at {}", logging::redact(&next)).into());
+            }
+            if url.starts_with("https://") && !next.starts_with("https://") {
+                return Err(format!("Refusing redirect from https to {}", logging::redact(&next)).into());
+            }
+            // User-supplied endpoints stay inside the URL policy after redirects too;
+            // built-in APIs have no business sending us to another host
+            let cross_host = !same_host(&url, &next);
+            if matches!(self.source_name, "custom" | "near_view") {
+                check_url_policy(&next, self.config)?;
+            } else if cross_host {
+                return Err(format!("Refusing cross-host redirect to {}", logging::redact(&next)).into());
+            }
+            // Credentials are meant for the host they were configured for
+            if cross_host {
+                headers.retain(|(name, _)| !is_credential_header(name));
+            }
+
+            // 303 (and 301/302 after a POST, as browsers do) continue as GET without a body
+            if status == 303 || (matches!(status, 301 | 302) && method == "POST") {
+                method = "GET";
+                body = None;
+            }
+
+            logging::info(self.source_name, &format!("HTTP {} redirect to {}", status, logging::redact(&next)));
+            visited.push(std::mem::replace(&mut url, next));
+        };
+
+        // Remaining-quota headers help tune request rates (e.g. CoinGecko x-ratelimit-*)
+        if self.config.debug_urls {
+            for (name, value) in response.headers() {
+                let name = name.to_lowercase();
+                if name.starts_with("x-ratelimit") || name == "retry-after" {
+                    self.detail.rate_limit.insert(name, value.clone());
+                }
+            }
+        }
+
+        let status = response.status();
+
+        // Parse JSON response (a non-JSON error body is reported by its status)
+        let body = response.body()?;
+        self.bytes_received += body.len();
+        let parse_start = Instant::now();
+        let text = match std::str::from_utf8(&body) {
+            Ok(text) => std::borrow::Cow::Borrowed(text),
+            Err(_) if self.lossy_decode => String::from_utf8_lossy(&body),
+            Err(e) => {
+                return Err(format!("source returned invalid UTF-8 (at byte {})", e.valid_up_to()).into());
+            }
+        };
+        // Raw body for debugging one source; secrets are redacted before truncating so none is cut in half
+        if self.config.capture_raw_for.iter().any(|name| name == self.source_name) {
+            self.detail.raw_response = Some(truncate_utf8(&logging::redact(&text), MAX_RAW_CAPTURE_BYTES));
+        }
+
+        let json: Value = match serde_json::from_str(&text) {
+            Ok(json) => json,
+            Err(_) if status < 200 || status >= 300 => return Err(format!("HTTP {}", status).into()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Accumulate across calls: some sources make more than one request
+        if self.config.perf_detail {
+            let bytes = self.detail.response_bytes.unwrap_or(0) + body.len();
+            let micros = self.detail.parse_micros.unwrap_or(0) + parse_start.elapsed().as_micros() as u64;
+            self.detail.response_bytes = Some(bytes);
+            self.detail.parse_micros = Some(micros);
+        }
+
+        Ok((status, json))
+    }
+
+    /// Send one request, sleeping through 429s (max_retries) and falling back to mirror hosts
+    /// on connection failure or HTTP 504
+    fn send_with_fallbacks(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<Response, Box<dyn Error>> {
+        // On connection failure, retry the same path on the source's mirror hosts
+        let mut candidates = vec![url.to_string()];
+        candidates.extend(mirror_urls(url));
+
+        let mut attempt = 0;
+        let mut retries = 0;
+        let response = loop {
+            let candidate = &candidates[attempt];
+            if self.config.debug_urls {
+                self.detail.urls.push(logging::redact(candidate));
+            }
+
+            match send_request(method, candidate, headers, body) {
+                // Rate limited: wait as told by Retry-After (or back off) and try again
+                Ok(response) if response.status() == 429 && retries < self.config.max_retries => {
+                    let backoff_ms = self.config.retry_backoff_ms.unwrap_or(DEFAULT_RETRY_BACKOFF_MS);
+                    let delay = retry_delay(&response, retries, backoff_ms);
+                    check_within_deadline(delay, self.deadline)?;
+                    logging::warn(self.source_name, &format!("HTTP 429, retrying in {}ms", delay.as_millis()));
+                    thread::sleep(delay);
+                    retries += 1;
+                }
+                // Gateway timeout (e.g. an IPFS gateway that could not retrieve the content in time)
+                Ok(response) if response.status() == 504 && attempt + 1 < candidates.len() => {
+                    logging::warn(self.source_name, &format!("{} returned HTTP 504, trying mirror", logging::redact(candidate)));
+                    attempt += 1;
+                }
+                Ok(response) => break response,
+                Err(e) if attempt + 1 < candidates.len() => {
+                    logging::warn(self.source_name, &format!("{} failed ({}), trying mirror", logging::redact(candidate), e));
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        if attempt > 0 {
+            self.detail.mirror = host_of(&candidates[attempt]).map(str::to_string);
+        }
+
+        Ok(response)
+    }
+}
+
+/// At most `max_bytes` of `text`, cut at a character boundary, with "..." when shortened
+fn truncate_utf8(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+// Redirect hops followed per request before giving up
+const MAX_REDIRECTS: usize = 5;
+
+/// Both URLs point at the same host (and port)
+fn same_host(a: &str, b: &str) -> bool {
+    host_of(a).zip(host_of(b)).is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+/// Headers carrying credentials (Authorization, Cookie, X-CMC_PRO_API_KEY, x-messari-api-key, ...)
+fn is_credential_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name == "authorization" || name == "cookie" || name.contains("api-key") || name.contains("api_key")
+}
+
+/// Case-insensitive response header lookup
+fn header_value<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response.headers()
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// Resolve an absolute URL, a scheme-relative "//host/path" or a host-relative "/path" against a base
+fn resolve_against(base_url: &str, target: &str) -> Option<String> {
+    if target.contains("://") {
+        return Some(target.to_string());
+    }
 
-/// Fetch price from CoinGecko
-pub fn fetch_coingecko(token_id: &str, api_key: Option<&str>) -> Result<SourcePrice, Box<dyn Error>> {
+    let (scheme, rest) = base_url.split_once("://")?;
+    if let Some(host_and_path) = target.strip_prefix("//") {
+        return Some(format!("{}://{}", scheme, host_and_path));
+    }
+    if target.starts_with('/') {
+        let host = rest.split('/').next()?;
+        return Some(format!("{}://{}{}", scheme, host, target));
+    }
+
+    None
+}
+
+/// Send one HTTP request (no status check or body parsing)
+fn send_request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&[u8]>,
+) -> Result<Response, Box<dyn Error>> {
+    let mut request = match method {
+        "GET" => Client::new().get(url),
+        "POST" => Client::new().post(url),
+        _ => return Err(format!("Unsupported HTTP method: {}", method).into()),
+    };
+
+    for (key, value) in headers {
+        request = request.header(*key, *value);
+    }
+
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+
+    Ok(request
+        .connect_timeout(Duration::from_secs(10))
+        .send()?)
+}
+
+// Longest Retry-After we are willing to sleep for within one invocation
+const MAX_RETRY_AFTER_SECS: u64 = 10;
+
+/// Delay before retrying a 429: Retry-After seconds if present (capped), else exponential backoff
+fn retry_delay(response: &Response, retries: u32, backoff_ms: u64) -> Duration {
+    let retry_after = header_value(response, "retry-after").and_then(|value| value.parse::<u64>().ok());
+
+    match retry_after {
+        Some(secs) => Duration::from_secs(secs.min(MAX_RETRY_AFTER_SECS)),
+        None => Duration::from_millis(backoff_ms.saturating_mul(1 << retries.min(16))),
+    }
+}
+
+/// Fail fast when a retry sleep would run past the overall deadline
+fn check_within_deadline(delay: Duration, deadline: Option<Instant>) -> Result<(), Box<dyn Error>> {
+    let Some(deadline) = deadline else {
+        return Ok(());
+    };
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if delay >= remaining {
+        return Err(format!(
+            "HTTP 429, retry in {}ms would pass the deadline ({}ms left)",
+            delay.as_millis(),
+            remaining.as_millis()
+        ).into());
+    }
+
+    Ok(())
+}
+
+/// Alternate hosts serving the same API paths as a primary host
+const HOST_MIRRORS: &[(&str, &[&str])] = &[
+    (
+        "api.binance.com",
+        &["api1.binance.com", "api2.binance.com", "api3.binance.com", "api4.binance.com", "api-gcp.binance.com"],
+    ),
+    ("api.huobi.pro", &["api-aws.huobi.pro"]),
+    // Gate.io v4 is also served from its original domain
+    ("api.gateio.ws", &["api.gate.io"]),
+    ("ipfs.io", &["dweb.link"]),
+];
+
+/// Mirror URLs for a request to a primary host (none when base_url_overrides changed the host)
+fn mirror_urls(url: &str) -> Vec<String> {
+    let host = match host_of(url) {
+        Some(host) => host,
+        None => return Vec::new(),
+    };
+
+    HOST_MIRRORS.iter()
+        .filter(|(primary, _)| *primary == host)
+        .flat_map(|(primary, mirrors)| {
+            mirrors.iter().map(move |mirror| url.replacen(primary, mirror, 1))
+        })
+        .collect()
+}
+
+/// Host part of a URL ("https://api.binance.com/x" -> "api.binance.com")
+fn host_of(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    rest.split(['/', '?']).next()
+}
+
+/// Fetch price from CoinGecko in `quote` (lowercase vs_currency, e.g. "usd", "eur")
+pub fn fetch_coingecko(token_id: &str, quote: &str, api_key: Option<&str>, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
     // Build URL - with or without API key
     let url = if let Some(key) = api_key {
         format!(
-            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd&x_cg_pro_api_key={}",
-            token_id, key
+            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies={}&x_cg_pro_api_key={}",
+            token_id, quote, key
         )
     } else {
         format!(
-            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd",
-            token_id
+            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies={}",
+            token_id, quote
         )
     };
 
-    // Make HTTP GET request
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
-
-    // Check status
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
-    }
-
-    // Parse JSON response
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
 
     // Extract price from response format: {"bitcoin": {"usd": 100000.0}}
     let price = json
         .get(token_id)
-        .and_then(|v| v.get("usd"))
+        .and_then(|v| v.get(quote))
         .and_then(|v| v.as_f64())
         .ok_or("Price not found in response")?;
 
@@ -49,48 +413,396 @@ pub fn fetch_coingecko(token_id: &str, api_key: Option<&str>) -> Result<SourcePr
         .duration_since(UNIX_EPOCH)?
         .as_secs();
 
-    Ok(SourcePrice {
-        source_name: "coingecko".to_string(),
-        value: DataValue::Number(price),
-        timestamp,
-    })
+    Ok(SourcePrice::new("coingecko".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// Fetch a token price from CoinGecko by contract address on a platform (e.g. "ethereum")
+/// For long-tail tokens without a known CoinGecko id; `quote` selects vs_currencies (default usd)
+pub fn fetch_coingecko_token_price(
+    platform: &str,
+    address: &str,
+    quote: &str,
+    api_key: Option<&str>,
+    ctx: &mut FetchContext,
+) -> Result<SourcePrice, Box<dyn Error>> {
+    let mut url = format!(
+        "https://api.coingecko.com/api/v3/simple/token_price/{}?contract_addresses={}&vs_currencies={}",
+        platform, address, quote
+    );
+    if let Some(key) = api_key {
+        url = format!("{}&x_cg_pro_api_key={}", url, key);
+    }
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Response keys are lowercased addresses: {"0xabc...": {"usd": 1.23}}
+    let price = json
+        .get(address.to_lowercase())
+        .and_then(|v| v.get(quote))
+        .and_then(|v| v.as_f64())
+        .ok_or("Price not found in response")?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(SourcePrice::new("coingecko".to_string(), DataValue::Number(price), timestamp))
+}
+
+// Built-in symbol -> CoinGecko id map for top coins (avoids downloading /coins/list)
+const COINGECKO_SYMBOL_IDS: &[(&str, &str)] = &[
+    ("BTC", "bitcoin"),
+    ("ETH", "ethereum"),
+    ("USDT", "tether"),
+    ("BNB", "binancecoin"),
+    ("SOL", "solana"),
+    ("USDC", "usd-coin"),
+    ("XRP", "ripple"),
+    ("DOGE", "dogecoin"),
+    ("ADA", "cardano"),
+    ("TRX", "tron"),
+    ("TON", "the-open-network"),
+    ("AVAX", "avalanche-2"),
+    ("DOT", "polkadot"),
+    ("LINK", "chainlink"),
+    ("NEAR", "near"),
+    ("LTC", "litecoin"),
+    ("BCH", "bitcoin-cash"),
+    ("UNI", "uniswap"),
+    ("ATOM", "cosmos"),
+    ("DAI", "dai"),
+    ("WBTC", "wrapped-bitcoin"),
+    ("XLM", "stellar"),
+    ("ETC", "ethereum-classic"),
+    ("APT", "aptos"),
+    ("ARB", "arbitrum"),
+    ("OP", "optimism"),
+    ("FIL", "filecoin"),
+];
+
+// CoinGecko /coins/list as (lowercase symbol, id), downloaded at most once per invocation
+static COINGECKO_COINS_LIST: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Resolve a ticker symbol (e.g. "BTC") to a CoinGecko id (e.g. "bitcoin")
+/// Uses the built-in map first, then CoinGecko's /coins/list (cached within the invocation)
+pub fn resolve_coingecko_symbol(
+    symbol: &str,
+    api_key: Option<&str>,
+    ctx: &mut FetchContext,
+) -> Result<String, Box<dyn Error>> {
+    if let Some((_, id)) = COINGECKO_SYMBOL_IDS.iter().find(|(s, _)| s.eq_ignore_ascii_case(symbol)) {
+        return Ok(id.to_string());
+    }
+
+    let coins = match COINGECKO_COINS_LIST.get() {
+        Some(coins) => coins,
+        None => {
+            let url = match api_key {
+                Some(key) => format!("https://api.coingecko.com/api/v3/coins/list?x_cg_pro_api_key={}", key),
+                None => "https://api.coingecko.com/api/v3/coins/list".to_string(),
+            };
+
+            // Response format: [{"id": "bitcoin", "symbol": "btc", "name": "Bitcoin"}, ...]
+            let json = ctx.get_json(&url, &[])?;
+            let coins: Vec<(String, String)> = json.as_array()
+                .ok_or("Coins list not found in response")?
+                .iter()
+                .filter_map(|c| {
+                    let symbol = c.get("symbol")?.as_str()?;
+                    let id = c.get("id")?.as_str()?;
+                    Some((symbol.to_lowercase(), id.to_string()))
+                })
+                .collect();
+
+            COINGECKO_COINS_LIST.get_or_init(|| coins)
+        }
+    };
+
+    let symbol = symbol.to_lowercase();
+    let matches: Vec<&str> = coins.iter()
+        .filter(|(s, _)| *s == symbol)
+        .map(|(_, id)| id.as_str())
+        .collect();
+
+    // Many tokens share a symbol; refuse to guess
+    match matches.as_slice() {
+        [] => Err(format!("Symbol '{}' not found in CoinGecko coins list", symbol).into()),
+        [id] => Ok(id.to_string()),
+        ids => Err(format!(
+            "Symbol '{}' is ambiguous on CoinGecko ({} matches, e.g. {}); use the id instead",
+            symbol,
+            ids.len(),
+            ids.iter().take(5).cloned().collect::<Vec<_>>().join(", ")
+        ).into()),
+    }
+}
+
+/// Results of batched calls made once per invocation, keyed by (source name, id)
+pub type BatchResults = HashMap<(String, String), Result<SourcePrice, String>>;
+
+/// Fetch prices for several CoinGecko ids with a single `ids=a,b,c` call
+/// Ids missing from the combined response are simply absent from the returned map
+pub fn fetch_coingecko_batch(
+    token_ids: &[String],
+    api_key: Option<&str>,
+    ctx: &mut FetchContext,
+) -> Result<HashMap<String, SourcePrice>, Box<dyn Error>> {
+    let ids = token_ids.join(",");
+
+    // Build URL - with or without API key
+    let url = if let Some(key) = api_key {
+        format!(
+            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd&x_cg_pro_api_key={}",
+            ids, key
+        )
+    } else {
+        format!(
+            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd",
+            ids
+        )
+    };
+
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
+
+    // Response format: {"bitcoin": {"usd": 100000.0}, "near": {"usd": 5.0}}
+    let mut prices = HashMap::new();
+    for token_id in token_ids {
+        if let Some(price) = json
+            .get(token_id)
+            .and_then(|v| v.get("usd"))
+            .and_then(|v| v.as_f64())
+        {
+            prices.insert(
+                token_id.clone(),
+                SourcePrice::new("coingecko".to_string(), DataValue::Number(price), timestamp),
+            );
+        }
+    }
+
+    Ok(prices)
+}
+
+/// Coalesce sources that support multi-id endpoints into one call per batch
+/// Only worth it when at least two distinct ids share the same source
+pub fn prefetch_batches(
+    requests: &[&DataRequest],
+    coingecko_key: Option<&str>,
+    twelvedata_key: Option<&str>,
+    config: &ExecutionConfig,
+    deadline: Option<Instant>,
+) -> BatchResults {
+    let mut results = BatchResults::new();
+
+    // Disallowed sources are never batched, so policy errors surface from the per-source path
+    let mut coingecko_ids: Vec<String> = Vec::new();
+    for data_req in requests {
+        // Symbol-keyed and contract-address sources are fetched individually
+        let coingecko_sources = data_req.sources.iter()
+            .filter(|s| config.resolve_source_name(&s.name) == "coingecko" && config.is_source_allowed("coingecko"))
+            .filter(|s| s.id_kind != IdKind::Symbol && s.platform.is_none() && is_default_quote(s));
+        for source in coingecko_sources {
+            let id = source.id.as_ref().unwrap_or(&data_req.id);
+            if !coingecko_ids.contains(id) {
+                coingecko_ids.push(id.clone());
+            }
+        }
+    }
+
+    if coingecko_ids.len() > 1 {
+        let mut ctx = FetchContext::new(config, "coingecko");
+        ctx.deadline = deadline;
+        match fetch_coingecko_batch(&coingecko_ids, coingecko_key, &mut ctx) {
+            Ok(mut prices) => {
+                for id in coingecko_ids {
+                    let result = prices
+                        .remove(&id)
+                        .ok_or_else(|| "Price not found in response".to_string());
+                    results.insert(("coingecko".to_string(), id), result);
+                }
+            }
+            Err(e) => {
+                let error = e.to_string();
+                for id in coingecko_ids {
+                    results.insert(("coingecko".to_string(), id), Err(error.clone()));
+                }
+            }
+        }
+    }
+
+    // Pyth Hermes accepts several ids[] per call
+    let mut pyth_ids: Vec<String> = Vec::new();
+    for data_req in requests {
+        let pyth_sources = data_req.sources.iter()
+            .filter(|s| config.resolve_source_name(&s.name) == "pyth" && config.is_source_allowed("pyth"))
+            .filter(|s| s.price_kind.is_spot());
+        for source in pyth_sources {
+            let id = source.id.as_ref().unwrap_or(&data_req.id);
+            if !pyth_ids.contains(id) {
+                pyth_ids.push(id.clone());
+            }
+        }
+    }
+
+    if pyth_ids.len() > 1 {
+        let mut ctx = FetchContext::new(config, "pyth");
+        ctx.deadline = deadline;
+        match fetch_pyth_batch(&pyth_ids, &mut ctx) {
+            Ok(prices) => {
+                for (id, result) in prices {
+                    results.insert(("pyth".to_string(), id), result);
+                }
+            }
+            Err(e) => {
+                let error = e.to_string();
+                for id in pyth_ids {
+                    results.insert(("pyth".to_string(), id), Err(error.clone()));
+                }
+            }
+        }
+    }
+
+    // TwelveData accepts a comma-separated symbol list on /price
+    let mut twelvedata_symbols: Vec<String> = Vec::new();
+    for data_req in requests {
+        let twelvedata_sources = data_req.sources.iter()
+            .filter(|s| config.resolve_source_name(&s.name) == "twelvedata" && config.is_source_allowed("twelvedata"));
+        for source in twelvedata_sources {
+            let symbol = source.id.as_ref().unwrap_or(&data_req.id);
+            if !twelvedata_symbols.contains(symbol) {
+                twelvedata_symbols.push(symbol.clone());
+            }
+        }
+    }
+
+    if twelvedata_symbols.len() > 1 {
+        let mut ctx = FetchContext::new(config, "twelvedata");
+        ctx.deadline = deadline;
+        match fetch_twelvedata_batch(&twelvedata_symbols, twelvedata_key, &mut ctx) {
+            Ok(prices) => {
+                for (symbol, result) in prices {
+                    results.insert(("twelvedata".to_string(), symbol), result);
+                }
+            }
+            Err(e) => {
+                let error = e.to_string();
+                for symbol in twelvedata_symbols {
+                    results.insert(("twelvedata".to_string(), symbol), Err(error.clone()));
+                }
+            }
+        }
+    }
+
+    // Coinbase rates return a whole currency row per base, so pairs sharing a base need one call
+    let mut coinbase_pairs: Vec<String> = Vec::new();
+    for data_req in requests {
+        let coinbase_sources = data_req.sources.iter()
+            .filter(|s| config.resolve_source_name(&s.name) == "coinbase_rates" && config.is_source_allowed("coinbase_rates"));
+        for source in coinbase_sources {
+            let pair = source.id.as_ref().unwrap_or(&data_req.id);
+            if !coinbase_pairs.contains(pair) {
+                coinbase_pairs.push(pair.clone());
+            }
+        }
+    }
+
+    // Only bases requested by more than one pair are worth prefetching
+    let mut bases: Vec<&str> = coinbase_pairs.iter()
+        .filter_map(|pair| pair.split_once('/').map(|(base, _)| base))
+        .collect();
+    bases.sort_unstable();
+    let mut shared_bases: Vec<&str> = bases.windows(2)
+        .filter(|pair| pair[0] == pair[1])
+        .map(|pair| pair[0])
+        .collect();
+    shared_bases.dedup();
+
+    for base in shared_bases {
+        let mut ctx = FetchContext::new(config, "coinbase_rates");
+        ctx.deadline = deadline;
+        let row = fetch_coinbase_rates(base, &mut ctx).map_err(|e| e.to_string());
+        for pair in coinbase_pairs.iter().filter(|p| p.split_once('/').map(|(b, _)| b) == Some(base)) {
+            let result = row.clone().and_then(|(rates, timestamp)| coinbase_pair_price(pair, &rates, timestamp));
+            results.insert(("coinbase_rates".to_string(), pair.clone()), result);
+        }
+    }
+
+    // NEAR view calls to the same RPC endpoint go out as one JSON-RPC batch
+    let mut view_calls: Vec<(String, &NearViewConfig, &str)> = Vec::new();
+    for data_req in requests {
+        let view_sources = data_req.sources.iter()
+            .filter(|s| config.resolve_source_name(&s.name) == "near_view" && config.is_source_allowed("near_view"));
+        for source in view_sources {
+            let (Some(view), id) = (&source.near_view, source.id.as_ref().unwrap_or(&data_req.id)) else {
+                continue;
+            };
+            let key = near_view_call_key(view, id);
+            if !view_calls.iter().any(|(k, _, _)| *k == key) {
+                view_calls.push((key, view, id));
+            }
+        }
+    }
+
+    let mut rpc_urls: Vec<&str> = view_calls.iter().map(|(_, view, _)| view.rpc_url.as_str()).collect();
+    rpc_urls.sort_unstable();
+    rpc_urls.dedup();
+
+    for rpc_url in rpc_urls {
+        let calls: Vec<(String, &NearViewConfig, &str)> = view_calls.iter()
+            .filter(|(_, view, _)| view.rpc_url == rpc_url)
+            .cloned()
+            .collect();
+        if calls.len() < 2 {
+            continue;
+        }
+
+        // A failed batch (e.g. an RPC without batch support) leaves each call to its own request
+        let mut ctx = FetchContext::new(config, "near_view");
+        ctx.deadline = deadline;
+        match fetch_near_view_batch(rpc_url, &calls, &mut ctx) {
+            Ok(prices) => {
+                for (key, result) in prices {
+                    results.insert(("near_view".to_string(), key), result);
+                }
+            }
+            Err(e) => logging::warn("near_view", &format!("batch failed, calling individually: {}", e)),
+        }
+    }
+
+    results
 }
 
-/// Fetch price from CoinMarketCap
-pub fn fetch_coinmarketcap(token_id: &str, api_key: Option<&str>) -> Result<SourcePrice, Box<dyn Error>> {
+/// Fetch price from CoinMarketCap in `quote` (uppercase convert currency, e.g. "USD", "EUR")
+pub fn fetch_coinmarketcap(token_id: &str, quote: &str, api_key: Option<&str>, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
     // CoinMarketCap requires API key
     let api_key = api_key.ok_or("CoinMarketCap requires API key")?;
 
     // Build URL
     let url = format!(
-        "https://pro-api.coinmarketcap.com/v1/cryptocurrency/quotes/latest?symbol={}&convert=USD",
-        token_id
+        "https://pro-api.coinmarketcap.com/v1/cryptocurrency/quotes/latest?symbol={}&convert={}",
+        token_id, quote
     );
 
-    // Make HTTP GET request with API key header
-    let response = Client::new()
-        .get(&url)
-        .header("X-CMC_PRO_API_KEY", api_key)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+    // Make HTTP GET request with API key header and parse JSON response
+    let (status, json) = ctx.send_json_any_status("GET", &url, &[("X-CMC_PRO_API_KEY", api_key)], None)?;
 
-    // Check status
-    let status = response.status();
+    // Failures are described in `status` (sometimes with HTTP 200), which says more than the HTTP code
+    if let Some(error) = cmc_status_error(&json) {
+        return Err(error.into());
+    }
     if status < 200 || status >= 300 {
         return Err(format!("HTTP {}", status).into());
     }
 
-    // Parse JSON response
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
-
     // Extract price from response format:
     // {"data": {"BTC": {"quote": {"USD": {"price": 100000.0}}}}}
     let price = json
         .get("data")
         .and_then(|v| v.get(token_id))
         .and_then(|v| v.get("quote"))
-        .and_then(|v| v.get("USD"))
+        .and_then(|v| v.get(quote))
         .and_then(|v| v.get("price"))
         .and_then(|v| v.as_f64())
         .ok_or("Price not found in response")?;
@@ -100,70 +812,111 @@ pub fn fetch_coinmarketcap(token_id: &str, api_key: Option<&str>) -> Result<Sour
         .duration_since(UNIX_EPOCH)?
         .as_secs();
 
-    Ok(SourcePrice {
-        source_name: "coinmarketcap".to_string(),
-        value: DataValue::Number(price),
-        
-        timestamp,
-    })
+    Ok(SourcePrice::new("coinmarketcap".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// "CoinMarketCap error 1001: This API Key is invalid." when `status.error_code` is non-zero
+fn cmc_status_error(response: &Value) -> Option<String> {
+    let status = response.get("status")?;
+    let code = status.get("error_code").and_then(|c| c.as_i64().or_else(|| c.as_str()?.parse().ok()))?;
+    if code == 0 {
+        return None;
+    }
+
+    let message = status.get("error_message").and_then(|m| m.as_str()).unwrap_or("no error message");
+    Some(format!("CoinMarketCap error {}: {}", code, message))
 }
 
 /// Fetch price from TwelveData (commodities, forex, crypto)
-pub fn fetch_twelvedata(token_id: &str, api_key: Option<&str>) -> Result<SourcePrice, Box<dyn Error>> {
-    // Build URL - with or without API key
-    let url = if let Some(key) = api_key {
-        format!(
-            "https://api.twelvedata.com/price?symbol={}&apikey={}",
-            token_id, key
-        )
-    } else {
-        // Free tier endpoint
-        format!(
-            "https://api.twelvedata.com/price?symbol={}",
-            token_id
-        )
-    };
+pub fn fetch_twelvedata(token_id: &str, api_key: Option<&str>, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = twelvedata_price_url(token_id, api_key);
 
-    // Make HTTP GET request
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
 
-    // Check status
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
-    }
+    // Response format: {"price": "1850.25"}
+    let price = twelvedata_price(&json)?;
 
-    // Parse JSON response
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    // Get current timestamp
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
 
-    // Extract price from response format: {"price": "1850.25"}
-    let price_str = json
-        .get("price")
-        .and_then(|v| v.as_str())
-        .ok_or("Price not found in response")?;
+    Ok(SourcePrice::new("twelvedata".to_string(), DataValue::Number(price), timestamp))
+}
 
-    let price: f64 = price_str.parse()?;
+/// Fetch prices for several TwelveData symbols with a single `symbol=A,B,C` call
+/// A symbol missing from the response or reported as an error fails only its own entry
+pub fn fetch_twelvedata_batch(
+    symbols: &[String],
+    api_key: Option<&str>,
+    ctx: &mut FetchContext,
+) -> Result<HashMap<String, Result<SourcePrice, String>>, Box<dyn Error>> {
+    let url = twelvedata_price_url(&symbols.join(","), api_key);
+    let json = ctx.get_json(&url, &[])?;
+
+    // Whole-call failures (bad key, rate limit) use the same envelope as a single symbol
+    if let Some(error) = twelvedata_error(&json) {
+        return Err(error.into());
+    }
 
-    // Get current timestamp
     let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)?
         .as_secs();
 
-    Ok(SourcePrice {
-        source_name: "twelvedata".to_string(),
-        value: DataValue::Number(price),
-        
-        timestamp,
-    })
+    // TwelveData changes the shape with the symbol count:
+    // one symbol is answered bare {"price": "1850.25"}, several as {"AAPL": {"price": "..."}, "MSFT": {...}}
+    Ok(symbols.iter()
+        .map(|symbol| {
+            let entry = if symbols.len() == 1 {
+                Some(&json)
+            } else {
+                json.get(symbol).or_else(|| {
+                    json.as_object()?.iter().find(|(key, _)| key.eq_ignore_ascii_case(symbol)).map(|(_, v)| v)
+                })
+            };
+            let result = entry
+                .ok_or_else(|| "Price not found in response".to_string())
+                .and_then(twelvedata_price)
+                .map(|price| SourcePrice::new("twelvedata".to_string(), DataValue::Number(price), timestamp));
+            (symbol.clone(), result)
+        })
+        .collect())
+}
+
+fn twelvedata_price_url(symbols: &str, api_key: Option<&str>) -> String {
+    match api_key {
+        Some(key) => format!("https://api.twelvedata.com/price?symbol={}&apikey={}", symbols, key),
+        // Free tier endpoint
+        None => format!("https://api.twelvedata.com/price?symbol={}", symbols),
+    }
+}
+
+/// Price of one symbol's entry: {"price": "1850.25"}
+fn twelvedata_price(entry: &Value) -> Result<f64, String> {
+    if let Some(error) = twelvedata_error(entry) {
+        return Err(error);
+    }
+
+    entry.get("price")
+        .and_then(value_as_f64)
+        .ok_or_else(|| "Price not found in response".to_string())
+}
+
+/// "TwelveData error 404: **symbol** not found: XYZ" for {"status": "error", "code": 404, "message": "..."}
+fn twelvedata_error(entry: &Value) -> Option<String> {
+    if entry.get("status").and_then(|v| v.as_str()) != Some("error") {
+        return None;
+    }
+
+    let code = entry.get("code").and_then(|v| v.as_i64()).unwrap_or(0);
+    let message = entry.get("message").and_then(|v| v.as_str()).unwrap_or("unknown error");
+    Some(format!("TwelveData error {}: {}", code, message))
 }
 
 /// Fetch exchange rate from ExchangeRate-API (free, no API key needed)
 /// Format: EUR/USD -> base=EUR, target=USD
-pub fn fetch_exchangerate_api(token_id: &str, _api_key: Option<&str>) -> Result<SourcePrice, Box<dyn Error>> {
+pub fn fetch_exchangerate_api(token_id: &str, _api_key: Option<&str>, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
     // Parse token_id format: "EUR/USD" -> base="EUR", target="USD"
     let parts: Vec<&str> = token_id.split('/').collect();
     if parts.len() != 2 {
@@ -176,21 +929,8 @@ pub fn fetch_exchangerate_api(token_id: &str, _api_key: Option<&str>) -> Result<
     // Build URL - free endpoint, no API key needed
     let url = format!("https://open.er-api.com/v6/latest/{}", base_currency);
 
-    // Make HTTP GET request
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
-
-    // Check status
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
-    }
-
-    // Parse JSON response
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
 
     // Extract rate from response format: {"rates": {"USD": 1.0542, ...}}
     let rate = json
@@ -204,29 +944,77 @@ pub fn fetch_exchangerate_api(token_id: &str, _api_key: Option<&str>) -> Result<
         .duration_since(UNIX_EPOCH)?
         .as_secs();
 
-    Ok(SourcePrice {
-        source_name: "exchangerate-api".to_string(),
-        value: DataValue::Number(rate),
-        timestamp,
-    })
+    Ok(SourcePrice::new("exchangerate-api".to_string(), DataValue::Number(rate), timestamp))
 }
 
-/// Fetch price from Binance
-pub fn fetch_binance(symbol: &str) -> Result<SourcePrice, Box<dyn Error>> {
-    let url = format!("https://api.binance.com/api/v3/ticker/price?symbol={}", symbol);
-
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+/// Fetch one row of exchange rates from Coinbase (keyless, crypto and fiat)
+/// Returns rates keyed by currency (units of that currency per 1 base) and the fetch time
+pub fn fetch_coinbase_rates(base: &str, ctx: &mut FetchContext) -> Result<(HashMap<String, f64>, u64), Box<dyn Error>> {
+    let url = format!("https://api.coinbase.com/v2/exchange-rates?currency={}", base);
+
+    // Invalid bases return 400 with {"errors": [{"id": "invalid_request", "message": "Invalid currency"}]}
+    let (status, json) = ctx.send_json_any_status("GET", &url, &[], None)?;
+    if let Some(error) = json.get("errors").and_then(|v| v.as_array()).and_then(|errors| errors.first()) {
+        let message = error.get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("Coinbase error: {}", message).into());
+    }
 
-    let status = response.status();
     if status < 200 || status >= 300 {
         return Err(format!("HTTP {}", status).into());
     }
 
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    // Response format: {"data": {"currency": "BTC", "rates": {"USD": "65000.12", "EUR": "60000.5", ...}}}
+    let rates = json.get("data")
+        .and_then(|v| v.get("rates"))
+        .and_then(|v| v.as_object())
+        .ok_or("Rates not found in response")?
+        .iter()
+        .filter_map(|(currency, rate)| {
+            rate.as_str()
+                .and_then(|s| s.parse::<f64>().ok())
+                .map(|rate| (currency.clone(), rate))
+        })
+        .collect();
+
+    // Coinbase doesn't report when the rates were computed
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok((rates, timestamp))
+}
+
+/// Pick a "BASE/TARGET" pair out of a Coinbase rates row
+fn coinbase_pair_price(pair: &str, rates: &HashMap<String, f64>, timestamp: u64) -> Result<SourcePrice, String> {
+    let (_, target) = pair.split_once('/')
+        .ok_or_else(|| format!("Invalid pair format: {}. Expected BASE/TARGET (e.g. BTC/USD)", pair))?;
+    let target = target.to_uppercase();
+
+    let rate = *rates.get(&target).ok_or_else(|| format!("Rate not found for {}", target))?;
+
+    let mut price = SourcePrice::new("coinbase_rates".to_string(), DataValue::Number(rate), timestamp);
+    if target != "USD" {
+        price.currency = Some(target);
+    }
+    Ok(price)
+}
+
+/// Fetch a single pair from Coinbase exchange rates
+/// Format: BTC/USD -> currency=BTC, read rates.USD
+pub fn fetch_coinbase_rate(pair: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let (base, _) = pair.split_once('/')
+        .ok_or_else(|| format!("Invalid pair format: {}. Expected BASE/TARGET (e.g. BTC/USD)", pair))?;
+
+    let (rates, timestamp) = fetch_coinbase_rates(base, ctx)?;
+    Ok(coinbase_pair_price(pair, &rates, timestamp)?)
+}
+
+/// Fetch price from Binance
+pub fn fetch_binance(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://api.binance.com/api/v3/ticker/price?symbol={}", symbol);
+
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
 
     let price = json
         .get("price")
@@ -236,29 +1024,85 @@ pub fn fetch_binance(symbol: &str) -> Result<SourcePrice, Box<dyn Error>> {
 
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 
-    Ok(SourcePrice {
-        source_name: "binance".to_string(),
-        value: DataValue::Number(price),
-        timestamp,
-    })
+    Ok(SourcePrice::new("binance".to_string(), DataValue::Number(price), timestamp))
 }
 
-/// Fetch price from Huobi
-pub fn fetch_huobi(symbol: &str) -> Result<SourcePrice, Box<dyn Error>> {
-    let url = format!("https://api.huobi.pro/market/detail/merged?symbol={}", symbol);
+/// Fetch price from Binance 24h ticker, stamped with the server's `closeTime`
+pub fn fetch_binance_24h(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://api.binance.com/api/v3/ticker/24hr?symbol={}", symbol);
 
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+    let json = ctx.get_json(&url, &[])?;
 
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
+    // Response format: {"lastPrice": "65000.10", "closeTime": 1729447200000, ...}
+    let price = json
+        .get("lastPrice")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or("Price not found in response")?;
+
+    let timestamp = json
+        .get("closeTime")
+        .and_then(|v| v.as_u64())
+        .map(|ms| ms / 1000)
+        .ok_or("closeTime not found in response")?;
+
+    Ok(SourcePrice::new("binance".to_string(), DataValue::Number(price), timestamp))
+}
+
+// Binance returns at most 1000 klines per call
+const MAX_CANDLES: u32 = 1000;
+
+/// Fetch average close of the last N Binance candles (mini-TWAP)
+/// Interval format: "1m", "5m", "1h", ...
+pub fn fetch_binance_candles(
+    symbol: &str,
+    candles: u32,
+    interval: &str,
+    ctx: &mut FetchContext,
+) -> Result<SourcePrice, Box<dyn Error>> {
+    if candles == 0 || candles > MAX_CANDLES {
+        return Err(format!("candles must be between 1 and {}", MAX_CANDLES).into());
     }
 
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    let url = format!(
+        "https://api.binance.com/api/v3/klines?symbol={}&interval={}&limit={}",
+        symbol, interval, candles
+    );
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Response format: [[openTime, open, high, low, close, volume, closeTime, ...], ...]
+    let klines = json.as_array().ok_or("Klines array not found in response")?;
+
+    let closes: Vec<f64> = klines.iter()
+        .filter_map(|k| k.get(4))
+        .filter_map(|v| v.as_str())
+        .filter_map(|s| s.parse::<f64>().ok())
+        .collect();
+
+    if closes.is_empty() || closes.len() != klines.len() {
+        return Err("Close price not found in klines".into());
+    }
+
+    let price = closes.iter().sum::<f64>() / closes.len() as f64;
+
+    // Freshness from the latest candle's close time (ms); the current candle closes in the future
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let close_time = klines.last()
+        .and_then(|k| k.get(6))
+        .and_then(|v| v.as_u64())
+        .map(|ms| ms / 1000)
+        .ok_or("Close time not found in klines")?;
+
+    Ok(SourcePrice::new("binance".to_string(), DataValue::Number(price), close_time.min(now)))
+}
+
+/// Fetch price from Huobi
+pub fn fetch_huobi(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://api.huobi.pro/market/detail/merged?symbol={}", symbol);
+
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
 
     // Get bid and ask prices
     let bid = json.get("tick")
@@ -278,29 +1122,15 @@ pub fn fetch_huobi(symbol: &str) -> Result<SourcePrice, Box<dyn Error>> {
 
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 
-    Ok(SourcePrice {
-        source_name: "huobi".to_string(),
-        value: DataValue::Number(price),
-        timestamp,
-    })
+    Ok(SourcePrice::new("huobi".to_string(), DataValue::Number(price), timestamp))
 }
 
 /// Fetch price from Crypto.com
-pub fn fetch_cryptocom(instrument: &str) -> Result<SourcePrice, Box<dyn Error>> {
+pub fn fetch_cryptocom(instrument: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
     let url = format!("https://api.crypto.com/v2/public/get-ticker?instrument_name={}", instrument);
 
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
-
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
-    }
-
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
 
     // Navigate to result.data[0] for the ticker data
     let data = json.get("result")
@@ -313,246 +1143,967 @@ pub fn fetch_cryptocom(instrument: &str) -> Result<SourcePrice, Box<dyn Error>>
         .and_then(|v| v.as_str())
         .and_then(|s| s.parse::<f64>().ok());
 
-    let ask = data.get("k")
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok());
+    let ask = data.get("k")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    let last = data.get("a")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    let price = match (bid, ask, last) {
+        (Some(b), Some(k), Some(a)) => (b + k + a) / 3.0,
+        (Some(b), Some(k), None) => (b + k) / 2.0,
+        (_, _, Some(a)) => a,
+        _ => return Err("Price not found in response".into()),
+    };
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(SourcePrice::new("cryptocom".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// Fetch price from KuCoin
+pub fn fetch_kucoin(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://api.kucoin.com/api/v1/market/orderbook/level1?symbol={}", symbol);
+
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
+
+    let bid = json.get("data")
+        .and_then(|v| v.get("bestBid"))
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    let ask = json.get("data")
+        .and_then(|v| v.get("bestAsk"))
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    let last = json.get("data")
+        .and_then(|v| v.get("price"))
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    let price = match (bid, ask, last) {
+        (Some(b), Some(a), Some(l)) => (b + a + l) / 3.0,
+        (Some(b), Some(a), None) => (b + a) / 2.0,
+        (_, _, Some(l)) => l,
+        _ => return Err("Price not found in response".into()),
+    };
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(SourcePrice::new("kucoin".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// Fetch price from KuCoin 24h stats, stamped with the server's `time`
+pub fn fetch_kucoin_24h(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://api.kucoin.com/api/v1/market/stats?symbol={}", symbol);
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Response format: {"data": {"time": 1729447200000, "buy": "...", "sell": "...", "last": "..."}}
+    let data = json.get("data").ok_or("Data not found in response")?;
+
+    let bid = data.get("buy").and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
+    let ask = data.get("sell").and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
+    let last = data.get("last").and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
+
+    let price = match (bid, ask, last) {
+        (Some(b), Some(a), Some(l)) => (b + a + l) / 3.0,
+        (Some(b), Some(a), None) => (b + a) / 2.0,
+        (_, _, Some(l)) => l,
+        _ => return Err("Price not found in response".into()),
+    };
+
+    let timestamp = data.get("time")
+        .and_then(|v| v.as_u64())
+        .map(|ms| ms / 1000)
+        .ok_or("Time not found in response")?;
+
+    Ok(SourcePrice::new("kucoin".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// Fetch price from Gate.io: the v4 spot API first, the deprecated v2 endpoint only if v4 fails
+/// Note: Gate's ticker endpoints carry no server timestamp, so this is stamped with local time
+pub fn fetch_gate(pair: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let price = match fetch_gate_v4(pair, ctx) {
+        Ok(price) => price,
+        Err(v4_error) => {
+            logging::warn("gate", &format!("v4 failed ({}), falling back to v2", v4_error));
+            fetch_gate_v2(pair, ctx)
+                .map_err(|v2_error| format!("v4: {}; v2: {}", v4_error, v2_error))?
+        }
+    };
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(SourcePrice::new("gate".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// Gate.io v4 spot ticker (`BTC_USDT`): `[{"last", "highest_bid", "lowest_ask", ...}]`
+fn fetch_gate_v4(pair: &str, ctx: &mut FetchContext) -> Result<f64, Box<dyn Error>> {
+    let url = format!("https://api.gateio.ws/api/v4/spot/tickers?currency_pair={}", pair.to_uppercase());
+
+    let json = ctx.get_json(&url, &[])?;
+    let ticker = json.get(0).ok_or("Ticker not found in response")?;
+
+    let field = |name: &str| ticker.get(name).and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
+    gate_ticker_price(field("highest_bid"), field("lowest_ask"), field("last"))
+}
+
+/// Deprecated Gate.io v2 ticker (`btc_usdt`): `{"result": "true", "last", "highestBid", "lowestAsk"}`
+fn fetch_gate_v2(pair: &str, ctx: &mut FetchContext) -> Result<f64, Box<dyn Error>> {
+    let url = format!("https://data.gateapi.io/api2/1/ticker/{}", pair.to_lowercase());
+
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
+
+    // Check if result is successful
+    check_success_when(&json, "result", &json!("true"))?;
+
+    let field = |name: &str| json.get(name).and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
+    gate_ticker_price(field("highestBid"), field("lowestAsk"), field("last"))
+}
+
+/// Blend Gate.io best bid, best ask and last trade (both API versions report the same three)
+fn gate_ticker_price(bid: Option<f64>, ask: Option<f64>, last: Option<f64>) -> Result<f64, Box<dyn Error>> {
+    match (bid, ask, last) {
+        (Some(b), Some(a), Some(l)) => Ok((b + a + l) / 3.0),
+        (Some(b), Some(a), None) => Ok((b + a) / 2.0),
+        (_, _, Some(l)) => Ok(l),
+        _ => Err("Price not found in response".into()),
+    }
+}
+
+/// Fetch price from Pyth Network
+pub fn fetch_pyth(price_id: &str, kind: &PriceKind, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://hermes.pyth.network/v2/updates/price/latest?ids[]={}", price_id);
+
+    // Make HTTP GET request and parse JSON response
+    let json = ctx.get_json(&url, &[])?;
+
+    let entry = find_pyth_entry(&json, price_id).ok_or("Price data not found")?;
+    parse_pyth_entry(entry, kind)
+}
+
+/// Fetch several Pyth price ids in one Hermes call (`ids[]=a&ids[]=b`)
+/// Results are keyed by the requested id string (spot prices only; EMA sources fetch individually)
+pub fn fetch_pyth_batch(
+    price_ids: &[String],
+    ctx: &mut FetchContext,
+) -> Result<HashMap<String, Result<SourcePrice, String>>, Box<dyn Error>> {
+    let query: Vec<String> = price_ids.iter().map(|id| format!("ids[]={}", id)).collect();
+    let url = format!("https://hermes.pyth.network/v2/updates/price/latest?{}", query.join("&"));
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Entries are matched by id; Hermes doesn't guarantee the request order
+    Ok(price_ids.iter()
+        .map(|id| {
+            let result = find_pyth_entry(&json, id)
+                .ok_or_else(|| "Price data not found".to_string())
+                .and_then(|entry| parse_pyth_entry(entry, &PriceKind::Spot).map_err(|e| e.to_string()));
+            (id.clone(), result)
+        })
+        .collect())
+}
+
+/// Find the `parsed` entry for a price id (Hermes reports ids lowercase without 0x)
+fn find_pyth_entry<'a>(json: &'a Value, price_id: &str) -> Option<&'a Value> {
+    let clean_id = price_id.strip_prefix("0x").unwrap_or(price_id).to_lowercase();
+
+    json.get("parsed")?
+        .as_array()?
+        .iter()
+        .find(|entry| entry.get("id").and_then(|v| v.as_str()) == Some(clean_id.as_str()))
+}
+
+/// Convert one Hermes `parsed` entry into a price (with freshness check and confidence)
+/// `price` and `ema_price` share the layout {price, conf, expo, publish_time}
+fn parse_pyth_entry(entry: &Value, kind: &PriceKind) -> Result<SourcePrice, Box<dyn Error>> {
+    let price_data = entry.get(kind.pyth_field()).ok_or("Price data not found")?;
+
+    let price_raw = price_data.get("price")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or("Price value not found")?;
+
+    let expo = price_data.get("expo")
+        .and_then(|v| v.as_i64())
+        .ok_or("Exponent not found")?;
+
+    let publish_time = price_data.get("publish_time")
+        .and_then(|v| v.as_u64())
+        .ok_or("Publish time not found")?;
+
+    // Check if price is fresh (within 120 seconds)
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if now.saturating_sub(publish_time) > 120 {
+        return Err(format!("Pyth price is stale (published {} seconds ago)", now - publish_time).into());
+    }
+
+    // Confidence interval uses the same exponent as the price
+    let conf_raw = price_data.get("conf")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok());
+
+    // Calculate decimal price
+    let scale = 10f64.powi(expo as i32);
+    let price = price_raw * scale;
+
+    let mut source_price = SourcePrice::new("pyth".to_string(), DataValue::Number(price), publish_time);
+    source_price.confidence = conf_raw.map(|c| c * scale);
+    Ok(source_price)
+}
+
+/// Fetch index price from Deribit (crypto derivatives reference)
+/// Index name format: "btc_usd", "eth_usd"
+pub fn fetch_deribit(index_name: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!(
+        "https://www.deribit.com/api/v2/public/get_index_price?index_name={}",
+        index_name
+    );
+
+    // Deribit returns JSON-RPC errors with 400, so read the body before checking status
+    let (status, json) = ctx.send_json_any_status("GET", &url, &[], None)?;
+
+    // Error envelope: {"error": {"message": "Invalid params", "code": -32602}}
+    if let Some(error) = json.get("error") {
+        let message = error.get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("Deribit error: {}", message).into());
+    }
+
+    if status < 200 || status >= 300 {
+        return Err(format!("HTTP {}", status).into());
+    }
+
+    // Response format: {"result": {"index_price": 65000.5, "timestamp": 1729447200000}}
+    let result = json.get("result").ok_or("Result not found in response")?;
+
+    let price = result.get("index_price")
+        .and_then(|v| v.as_f64())
+        .ok_or("Index price not found in response")?;
+
+    // Deribit timestamps are in milliseconds
+    let timestamp = result.get("timestamp")
+        .and_then(|v| v.as_u64())
+        .map(|ms| ms / 1000)
+        .ok_or("Timestamp not found in response")?;
+
+    Ok(SourcePrice::new("deribit".to_string(), DataValue::Number(price), timestamp))
+}
+
+/// Rewrite "ipfs://<cid>/<path>" to the configured HTTP gateway; other URLs are returned unchanged
+/// The legacy "ipfs://ipfs/<cid>" form is accepted too
+fn ipfs_gateway_url(url: &str, config: &ExecutionConfig) -> Result<String, Box<dyn Error>> {
+    let rest = match url.strip_prefix("ipfs://") {
+        Some(rest) => rest.strip_prefix("ipfs/").unwrap_or(rest),
+        None => return Ok(url.to_string()),
+    };
+
+    let cid = rest.split(['/', '?', '#']).next().unwrap_or("");
+    if cid.is_empty() || !cid.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid IPFS URL '{}': expected ipfs://<cid>/<path>", url).into());
+    }
+
+    let gateway = config.ipfs_gateway.as_deref().unwrap_or(DEFAULT_IPFS_GATEWAY);
+    Ok(format!("{}/{}", gateway.trim_end_matches('/'), rest))
+}
+
+/// Check a custom source URL against the execution URL policy (SSRF protection)
+/// Rejects non-https URLs unless `allow_insecure_urls` is set, and loopback/private/link-local
+/// hosts unless listed in `url_allowlist`
+pub fn check_url_policy(url: &str, config: &ExecutionConfig) -> Result<(), Box<dyn Error>> {
+    let (scheme, rest) = url.split_once("://")
+        .ok_or_else(|| format!("Blocked URL: '{}' has no scheme", url))?;
+
+    // Host is between "://" and the first '/', '?' or '#', without userinfo and port
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host_port = authority.rsplit('@').next().unwrap_or("");
+    let host = if let Some(bracketed) = host_port.strip_prefix('[') {
+        // IPv6 literal: [::1]:8080
+        bracketed.split(']').next().unwrap_or("")
+    } else {
+        host_port.split(':').next().unwrap_or("")
+    }
+    .to_lowercase();
+
+    if host.is_empty() {
+        return Err(format!("Blocked URL: '{}' has no host", url).into());
+    }
+
+    // Explicitly allowed hosts (e.g. internal RPC endpoints) skip the remaining checks
+    if config.url_allowlist.iter().any(|allowed| allowed.eq_ignore_ascii_case(&host)) {
+        return Ok(());
+    }
+
+    if !scheme.eq_ignore_ascii_case("https") && !config.allow_insecure_urls {
+        return Err(format!("Blocked URL: '{}' must use https", url).into());
+    }
+
+    if is_internal_host(&host) {
+        return Err(format!("Blocked URL: '{}' targets a local or private address", url).into());
+    }
+
+    Ok(())
+}
+
+/// Loopback, private, link-local and unspecified addresses (including decimal IPv4 like 2130706433)
+fn is_internal_host(host: &str) -> bool {
+    if host == "localhost" || host.ends_with(".localhost") || host.ends_with(".internal") {
+        return true;
+    }
+
+    let ip = match host.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => match host.parse::<u32>() {
+            Ok(n) => IpAddr::V4(Ipv4Addr::from(n)),
+            Err(_) => return false,
+        },
+    };
+
+    match ip {
+        IpAddr::V4(v4) => {
+            v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_unspecified()
+                || v4.is_broadcast()
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_internal_host(&v4.to_string());
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback() || v6.is_unspecified()
+                || (first & 0xfe00) == 0xfc00 // unique local fc00::/7
+                || (first & 0xffc0) == 0xfe80 // link-local fe80::/10
+        }
+    }
+}
+
+/// Fetch reference price from Messari (asset slug, e.g. "bitcoin")
+pub fn fetch_messari(asset_slug: &str, api_key: Option<&str>, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://data.messari.io/api/v1/assets/{}/metrics/market-data", asset_slug);
+
+    // API key is optional (higher rate limits)
+    let headers: Vec<(&str, &str)> = match api_key {
+        Some(key) => vec![("x-messari-api-key", key)],
+        None => Vec::new(),
+    };
+
+    let json = ctx.get_json(&url, &headers)?;
+
+    // Response format: {"data": {"market_data": {"price_usd": 65000.1, "last_trade_at": "2024-10-20T12:34:56.789Z"}}}
+    let market_data = json.get("data")
+        .and_then(|v| v.get("market_data"))
+        .ok_or("Market data not found in response")?;
+
+    let price = market_data.get("price_usd")
+        .and_then(|v| v.as_f64())
+        .ok_or("Price not found in response")?;
+
+    let timestamp = market_data.get("last_trade_at")
+        .and_then(|v| v.as_str())
+        .and_then(timefmt::parse_rfc3339)
+        .ok_or("last_trade_at not found in response")?;
+
+    Ok(SourcePrice::new("messari".to_string(), DataValue::Number(price), timestamp))
+}
+
+// Order book levels per side used for the Binance imbalance signal
+const IMBALANCE_LEVELS: usize = 5;
+
+/// Order book imbalance from Binance depth: (bidSize - askSize) / (bidSize + askSize)
+/// over the top IMBALANCE_LEVELS levels; ranges from -1 (all asks) to 1 (all bids)
+pub fn fetch_binance_imbalance(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!(
+        "https://api.binance.com/api/v3/depth?symbol={}&limit={}",
+        symbol, IMBALANCE_LEVELS
+    );
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Response format: {"lastUpdateId": 1, "bids": [["65000.1", "0.5"], ...], "asks": [["65000.2", "1.2"], ...]}
+    let bid_size = total_level_size(json.get("bids").ok_or("Bids not found in response")?, "bids")?;
+    let ask_size = total_level_size(json.get("asks").ok_or("Asks not found in response")?, "asks")?;
+
+    let total = bid_size + ask_size;
+    if total <= 0.0 {
+        return Err("Order book is empty".into());
+    }
+
+    let imbalance = (bid_size - ask_size) / total;
+
+    // Depth snapshots carry no timestamp
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(SourcePrice::new("binance_imbalance".to_string(), DataValue::Number(imbalance), timestamp))
+}
+
+/// Sum of sizes over the top IMBALANCE_LEVELS `[price, size]` levels of a book side
+fn total_level_size(side: &Value, label: &str) -> Result<f64, Box<dyn Error>> {
+    let entries = side.as_array().ok_or_else(|| format!("Depth {} is not an array", label))?;
+
+    entries.iter()
+        .take(IMBALANCE_LEVELS)
+        .map(|level| {
+            level.get(1)
+                .and_then(value_as_f64)
+                .ok_or_else(|| format!("Depth {} level is not [price, size]", label).into())
+        })
+        .sum()
+}
+
+/// Current perpetual funding rate from OKX, as a fraction per funding interval (0.0001 = 0.01%)
+/// Instrument format: "BTC-USD-SWAP", "ETH-USDT-SWAP"; the rate can be negative
+pub fn fetch_okx_funding(inst_id: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!(
+        "https://www.okx.com/api/v5/public/funding-rate?instId={}",
+        inst_id.to_uppercase()
+    );
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Errors come back as 200 with {"code": "51001", "msg": "Instrument ID does not exist", "data": []}
+    let code = json.get("code").and_then(|v| v.as_str()).unwrap_or("");
+    if code != "0" {
+        let message = json.get("msg")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("OKX error {}: {}", code, message).into());
+    }
+
+    // Response format: {"code": "0", "data": [{"instId": "BTC-USD-SWAP", "fundingRate": "0.0001", "fundingTime": "1729468800000", "ts": "1729447200000"}]}
+    let entry = json.get("data")
+        .and_then(|v| v.as_array())
+        .and_then(|data| data.first())
+        .ok_or("Funding rate not found in response")?;
+
+    let rate = entry.get("fundingRate")
+        .and_then(value_as_f64)
+        .ok_or("Funding rate is not a number")?;
+
+    // "ts" is when the rate was computed (ms); "fundingTime" is the next settlement, so it isn't used
+    let timestamp = match entry.get("ts").and_then(value_as_f64) {
+        Some(ms) => (ms / 1000.0) as u64,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+
+    Ok(SourcePrice::new("okx_funding".to_string(), DataValue::Number(rate), timestamp))
+}
+
+/// Current perpetual funding rate from Bybit linear contracts, as a fraction per funding interval
+/// Symbol format: "BTCUSDT", "ETHUSDT"; the rate can be negative
+pub fn fetch_bybit_funding(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!(
+        "https://api.bybit.com/v5/market/tickers?category=linear&symbol={}",
+        symbol.to_uppercase()
+    );
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Errors come back as 200 with {"retCode": 10001, "retMsg": "Not supported symbols", "result": {}}
+    let ret_code = json.get("retCode").and_then(|v| v.as_i64()).unwrap_or(-1);
+    if ret_code != 0 {
+        let message = json.get("retMsg")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("Bybit error {}: {}", ret_code, message).into());
+    }
+
+    // Response format: {"retCode": 0, "result": {"list": [{"symbol": "BTCUSDT", "fundingRate": "0.0001", ...}]}, "time": 1729447200000}
+    let entry = json.get("result")
+        .and_then(|v| v.get("list"))
+        .and_then(|v| v.as_array())
+        .and_then(|list| list.first())
+        .ok_or("Ticker not found in response")?;
+
+    let rate = entry.get("fundingRate")
+        .and_then(value_as_f64)
+        .ok_or("Funding rate is not a number")?;
+
+    // Bybit timestamps are in milliseconds
+    let timestamp = match json.get("time").and_then(|v| v.as_u64()) {
+        Some(ms) => ms / 1000,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+
+    Ok(SourcePrice::new("bybit_funding".to_string(), DataValue::Number(rate), timestamp))
+}
+
+/// Fetch KRW price from Bithumb (Korean exchange)
+/// Symbol format: "BTC", "ETH" (quoted in KRW)
+pub fn fetch_bithumb(symbol: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("https://api.bithumb.com/public/ticker/{}_KRW", symbol.to_uppercase());
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Errors come back as 200 with {"status": "5500", "message": "Invalid Parameter"}
+    let status = json.get("status").and_then(|v| v.as_str()).unwrap_or("");
+    if status != "0000" {
+        let message = json.get("message")
+            .and_then(|v| v.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("Bithumb error {}: {}", status, message).into());
+    }
+
+    // Response format: {"status": "0000", "data": {"closing_price": "91000000", "date": "1729447200000", ...}}
+    // The ticker has no bid/ask fields, so the last trade is the only price
+    let data = json.get("data").ok_or("Data not found in response")?;
+    let price = data.get("closing_price")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<f64>().ok())
+        .ok_or("Price not found in response")?;
+
+    // Bithumb timestamps are in milliseconds (as a string)
+    let timestamp = data.get("date")
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<u64>().ok())
+        .map(|ms| ms / 1000)
+        .ok_or("Timestamp not found in response")?;
+
+    let mut source_price = SourcePrice::new("bithumb".to_string(), DataValue::Number(price), timestamp);
+    source_price.currency = Some("KRW".to_string());
+    Ok(source_price)
+}
+
+// Chainlink data feeds REST endpoint (feed path appended); point base_url_overrides at a proxy to replace it
+const CHAINLINK_REST_URL: &str = "https://data.chain.link/api/feeds";
+
+// Reject Chainlink answers older than this (the longest standard feed heartbeat is 24h)
+const CHAINLINK_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+
+/// Fetch a Chainlink feed answer over REST (no RPC / ABI decoding)
+/// Feed format: "eth-usd", "btc-usd" (as named by the API or proxy)
+pub fn fetch_chainlink_rest(feed: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = format!("{}/{}", CHAINLINK_REST_URL, feed);
+
+    let json = ctx.get_json(&url, &[])?;
+
+    // Response format: {"answer": "6500012345678", "decimals": 8, "updatedAt": 1729447200}
+    // Large answers may be strings to keep integer precision
+    let answer = json.get("answer")
+        .and_then(|v| v.as_f64().or_else(|| v.as_str().and_then(|s| s.parse::<f64>().ok())))
+        .ok_or("Answer not found in response")?;
+
+    let decimals = json.get("decimals")
+        .and_then(|v| v.as_u64())
+        .ok_or("Decimals not found in response")?;
+
+    let updated_at = json.get("updatedAt")
+        .and_then(|v| v.as_u64().or_else(|| v.as_str().and_then(|s| s.parse::<u64>().ok())))
+        .ok_or("updatedAt not found in response")?;
+
+    // A feed that stopped updating still returns its last answer
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let age = now.saturating_sub(updated_at);
+    if age > CHAINLINK_MAX_AGE_SECS {
+        return Err(format!("Chainlink feed {} is stale ({}s old)", feed, age).into());
+    }
+
+    let price = answer / 10f64.powi(decimals as i32);
+
+    Ok(SourcePrice::new("chainlink_rest".to_string(), DataValue::Number(price), updated_at))
+}
+
+/// Fetch price from custom user-defined source
+pub fn fetch_custom(
+    config: &CustomSourceConfig,
+    verify: Option<&SigConfig>,
+    ctx: &mut FetchContext,
+) -> Result<SourcePrice, Box<dyn Error>> {
+    let url = ipfs_gateway_url(&config.url, ctx.config)?;
+    check_url_policy(&url, ctx.config)?;
+    ctx.lossy_decode = config.lossy_decode && matches!(config.value_type, ValueType::String);
 
-    let last = data.get("a")
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok());
+    if config.graphql.is_some() && config.paginate.is_some() {
+        return Err("paginate is not supported for GraphQL sources".into());
+    }
 
-    let price = match (bid, ask, last) {
-        (Some(b), Some(k), Some(a)) => (b + k + a) / 3.0,
-        (Some(b), Some(k), None) => (b + k) / 2.0,
-        (_, _, Some(a)) => a,
-        _ => return Err("Price not found in response".into()),
-    };
+    // GraphQL is always a POST of {query, variables}
+    let method = if config.graphql.is_some() { "POST".to_string() } else { config.method.to_uppercase() };
+    let mut headers: Vec<(&str, &str)> = config.headers
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
 
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let body_str = custom_request_body(config, &method)?;
 
-    Ok(SourcePrice {
-        source_name: "cryptocom".to_string(),
-        value: DataValue::Number(price),
-        timestamp,
-    })
-}
+    // Auto-add Content-Type header if not already provided
+    if body_str.is_some() && !config.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("content-type")) {
+        headers.push(("Content-Type", "application/json"));
+    }
 
-/// Fetch price from KuCoin
-pub fn fetch_kucoin(symbol: &str) -> Result<SourcePrice, Box<dyn Error>> {
-    let url = format!("https://api.kucoin.com/api/v1/market/orderbook/level1?symbol={}", symbol);
+    // Auto-add Authorization Bearer if API_KEY is in environment
+    let auth_header = env::var("API_KEY").ok().map(|api_key| format!("Bearer {}", api_key));
+    if let Some(auth) = &auth_header {
+        headers.push(("Authorization", auth.as_str()));
+    }
 
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+    // Send request and parse JSON response (accept_status whitelists extra non-2xx codes)
+    let (status, response) = ctx.send_json_any_status(&method, &url, &headers, body_str.as_ref().map(|b| b.as_bytes()))?;
 
-    let status = response.status();
-    if status < 200 || status >= 300 {
+    // GraphQL reports query failures in `errors` (often with HTTP 200), separately from the value path
+    if config.graphql.is_some() {
+        if let Some(message) = graphql_error(&response) {
+            return Err(format!("GraphQL error: {}", message).into());
+        }
+    }
+
+    let accepted = config.accept_status.as_ref().is_some_and(|codes| codes.contains(&status));
+    if (status < 200 || status >= 300) && !accepted {
         return Err(format!("HTTP {}", status).into());
     }
 
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    // GraphQL paths are relative to `data`
+    let json = match &config.graphql {
+        Some(_) => response.get("data").filter(|d| !d.is_null()).ok_or("GraphQL response has no data")?,
+        None => &response,
+    };
 
-    let bid = json.get("data")
-        .and_then(|v| v.get("bestBid"))
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok());
+    // Signed sources: nothing is extracted until the signature checks out
+    if let Some(verify) = verify {
+        let signed_by = verify_signed_json(json, verify, &config.json_path)
+            .map_err(|e| format!("Signature verification failed: {}", e))?;
+        ctx.detail.signed_by = Some(signed_by);
+    }
 
-    let ask = json.get("data")
-        .and_then(|v| v.get("bestAsk"))
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok());
+    // Some APIs answer 200 and report failure in a status field
+    if let Some((path, expected)) = &config.success_when {
+        check_success_when(json, path, expected)?;
+    }
 
-    let last = json.get("data")
-        .and_then(|v| v.get("price"))
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok());
+    // Extract value: depth-aware mid, values reduced across pages, or JSON path (e.g. "data.price")
+    let value = match (&config.depth, &config.paginate) {
+        (Some(depth), _) => DataValue::Number(calculate_depth_mid(json, depth)?),
+        (None, Some(paginate)) => {
+            DataValue::Number(collect_pages(config, paginate, &url, json, &headers, ctx)?)
+        }
+        (None, None) => match (&config.series_reduce, &config.strip_chars) {
+            (Some(series), _) => DataValue::Number(reduce_series(json, &config.json_path, series)?),
+            (None, Some(chars)) => extract_stripped_value(json, &config.json_path, &config.value_type, chars)?,
+            (None, None) => extract_json_value(json, &config.json_path, &config.value_type)?,
+        },
+    };
 
-    let price = match (bid, ask, last) {
-        (Some(b), Some(a), Some(l)) => (b + a + l) / 3.0,
-        (Some(b), Some(a), None) => (b + a) / 2.0,
-        (_, _, Some(l)) => l,
-        _ => return Err("Price not found in response".into()),
+    // Optional confidence/uncertainty reported by the source
+    let confidence = match &config.confidence_path {
+        Some(path) => match extract_json_value(json, path, &ValueType::Number)? {
+            DataValue::Number(c) => Some(c),
+            _ => None,
+        },
+        None => None,
     };
 
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    // Get current timestamp
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs();
 
-    Ok(SourcePrice {
-        source_name: "kucoin".to_string(),
-        value: DataValue::Number(price),
-        timestamp,
-    })
+    let mut source_price = SourcePrice::new("custom".to_string(), value, timestamp);
+    source_price.confidence = confidence;
+    Ok(source_price)
 }
 
-/// Fetch price from Gate.io
-pub fn fetch_gate(pair: &str) -> Result<SourcePrice, Box<dyn Error>> {
-    let url = format!("https://data.gateapi.io/api2/1/ticker/{}", pair);
+/// Serialized request body: the GraphQL envelope, or `body` for POST (e.g. a snapshot subscription)
+fn custom_request_body(config: &CustomSourceConfig, method: &str) -> Result<Option<String>, serde_json::Error> {
+    match (&config.graphql, method, &config.body) {
+        (Some(graphql), _, _) => Ok(Some(json!({"query": graphql.query, "variables": graphql.variables}).to_string())),
+        (None, "POST", Some(body)) => serde_json::to_string(body).map(Some),
+        _ => Ok(None),
+    }
+}
 
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+/// Require the value at `path` to match `expected`; a string and a number or boolean match when
+/// their text is equal, so `"true"` accepts both `true` and `"true"`
+fn check_success_when(json: &Value, path: &str, expected: &Value) -> Result<(), Box<dyn Error>> {
+    let actual = navigate_json_path(json, path)
+        .map_err(|_| format!("Source reported no status: '{}' not found (expected {})", path, expected))?;
 
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
+    let as_text = |value: &Value| match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+        _ => None,
+    };
+    if actual == expected || as_text(actual).is_some_and(|text| Some(text) == as_text(expected)) {
+        return Ok(());
     }
 
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    Err(format!("Source reported failure: '{}' is {}, expected {}", path, actual, expected).into())
+}
 
-    // Check if result is successful
-    let result = json.get("result")
-        .and_then(|v| v.as_str())
-        .ok_or("Result not found")?;
+/// Messages from a GraphQL `errors` array, joined ("a; b"), if the array is non-empty
+fn graphql_error(response: &Value) -> Option<String> {
+    let errors = response.get("errors")?.as_array().filter(|e| !e.is_empty())?;
+
+    let messages: Vec<String> = errors.iter()
+        .map(|e| e.get("message").and_then(|m| m.as_str()).map(str::to_string).unwrap_or_else(|| e.to_string()))
+        .collect();
+    Some(messages.join("; "))
+}
 
-    if result != "true" {
-        return Err("Gate.io API returned unsuccessful result".into());
+/// Phase one of a discovery request: the ids listed at `json_path` in the directory response
+pub fn fetch_discovery_list(directory: &CustomSourceConfig, config: &ExecutionConfig, deadline: Option<Instant>) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut ctx = FetchContext::new(config, "discovery");
+    ctx.deadline = deadline;
+    let url = ipfs_gateway_url(&directory.url, config)?;
+    check_url_policy(&url, config)?;
+
+    let method = directory.method.to_uppercase();
+    let mut headers: Vec<(&str, &str)> = directory.headers
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let body_str = match (method.as_str(), &directory.body) {
+        ("POST", Some(body)) => Some(serde_json::to_string(body)?),
+        _ => None,
+    };
+    if body_str.is_some() && !directory.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("content-type")) {
+        headers.push(("Content-Type", "application/json"));
     }
 
-    let bid = json.get("highestBid").and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
-    let ask = json.get("lowestAsk").and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
-    let last = json.get("last").and_then(|v| v.as_str()).and_then(|s| s.parse::<f64>().ok());
+    let (status, json) = ctx.send_json_any_status(&method, &url, &headers, body_str.as_ref().map(|b| b.as_bytes()))?;
+    if status < 200 || status >= 300 {
+        return Err(format!("HTTP {}", status).into());
+    }
 
-    let price = match (bid, ask, last) {
-        (Some(b), Some(a), Some(l)) => (b + a + l) / 3.0,
-        (Some(b), Some(a), None) => (b + a) / 2.0,
-        (_, _, Some(l)) => l,
-        _ => return Err("Price not found in response".into()),
+    let list = if directory.json_path.is_empty() { &json } else { navigate_json_path(&json, &directory.json_path)? };
+    list.as_array()
+        .ok_or_else(|| format!("JSON path '{}' is not an array", directory.json_path))?
+        .iter()
+        .map(|entry| match entry {
+            Value::String(id) => Ok(id.clone()),
+            Value::Number(id) => Ok(id.to_string()),
+            other => Err(format!("discovered entry {} is not a string or number", other).into()),
+        })
+        .collect()
+}
+
+/// Check the signature embedded in a response against `allowed_pubkeys`, returning the signer
+fn verify_signed_json(json: &Value, verify: &SigConfig, json_path: &str) -> Result<String, Box<dyn Error>> {
+    let as_str = |path: &str| -> Result<String, Box<dyn Error>> {
+        navigate_json_path(json, path)?
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("'{}' is not a string", path).into())
     };
+    let signature = as_str(&verify.signature_path)?;
+    let pubkey = as_str(&verify.pubkey_path)?;
 
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let message = match navigate_json_path(json, verify.message_path.as_deref().unwrap_or(json_path))? {
+        Value::String(text) => text.as_bytes().to_vec(),
+        other => serde_json::to_vec(other)?,
+    };
 
-    Ok(SourcePrice {
-        source_name: "gate".to_string(),
-        value: DataValue::Number(price),
-        timestamp,
-    })
+    Ok(signing::verify_source_signature(&message, &signature, &pubkey, &verify.allowed_pubkeys)?)
 }
 
-/// Fetch price from Pyth Network
-pub fn fetch_pyth(price_id: &str) -> Result<SourcePrice, Box<dyn Error>> {
-    // Remove 0x prefix if present
-    let clean_id = price_id.strip_prefix("0x").unwrap_or(price_id);
-    let url = format!("https://hermes.pyth.network/v2/updates/price/latest?ids[]={}", price_id);
+/// Follow `next_path` links from the first page, collecting `json_path` values from every page
+/// Fails rather than truncating when the page or byte cap is hit, since a partial sum is wrong
+fn collect_pages(
+    config: &CustomSourceConfig,
+    paginate: &Paginate,
+    base_url: &str,
+    first_page: &Value,
+    headers: &[(&str, &str)],
+    ctx: &mut FetchContext,
+) -> Result<f64, Box<dyn Error>> {
+    let mut values = collect_page_values(first_page, &config.json_path)?;
+    let mut next = next_page_url(first_page, &paginate.next_path, base_url);
+    let mut pages = 1;
+
+    while let Some(url) = next {
+        if pages >= paginate.max_pages {
+            return Err(format!("Pagination exceeded max_pages ({})", paginate.max_pages).into());
+        }
+        check_url_policy(&url, ctx.config)?;
 
-    let response = Client::new()
-        .get(&url)
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+        let (status, page) = ctx.send_json_any_status("GET", &url, headers, None)?;
+        let accepted = config.accept_status.as_ref().is_some_and(|codes| codes.contains(&status));
+        if (status < 200 || status >= 300) && !accepted {
+            return Err(format!("HTTP {} on page {}", status, pages + 1).into());
+        }
+        if ctx.bytes_received > paginate.max_total_bytes {
+            return Err(format!("Pagination exceeded max_total_bytes ({})", paginate.max_total_bytes).into());
+        }
 
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
+        values.extend(collect_page_values(&page, &config.json_path)?);
+        next = next_page_url(&page, &paginate.next_path, base_url);
+        pages += 1;
     }
 
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
-
-    // Get price data from parsed array
-    let price_data = json.get("parsed")
-        .and_then(|v| v.get(0))
-        .and_then(|v| v.get("price"))
-        .ok_or("Price data not found")?;
+    if values.is_empty() && !matches!(paginate.reduce, PageReduce::Count) {
+        return Err("No values collected across pages".into());
+    }
 
-    let price_raw = price_data.get("price")
-        .and_then(|v| v.as_str())
-        .and_then(|s| s.parse::<f64>().ok())
-        .ok_or("Price value not found")?;
+    let result = match paginate.reduce {
+        PageReduce::Sum => values.iter().sum(),
+        PageReduce::Average => values.iter().sum::<f64>() / values.len() as f64,
+        PageReduce::Min => values.iter().cloned().fold(f64::MAX, f64::min),
+        PageReduce::Max => values.iter().cloned().fold(f64::MIN, f64::max),
+        PageReduce::Count => values.len() as f64,
+    };
+    Ok(result)
+}
 
-    let expo = price_data.get("expo")
-        .and_then(|v| v.as_i64())
-        .ok_or("Exponent not found")?;
+/// Reduce the samples at `path` (e.g. "trades.*.price") to one value
+fn reduce_series(json: &Value, path: &str, series: &SeriesReduce) -> Result<f64, Box<dyn Error>> {
+    let values = collect_page_values(json, path)?;
+    if values.is_empty() {
+        return Err(format!("Series at '{}' is empty", path).into());
+    }
 
-    let publish_time = price_data.get("publish_time")
-        .and_then(|v| v.as_u64())
-        .ok_or("Publish time not found")?;
+    match series.method {
+        SeriesMethod::Average => Ok(values.iter().sum::<f64>() / values.len() as f64),
+        SeriesMethod::TimeWeighted => {
+            let time_path = series.time_path.as_deref().ok_or("time_weighted series_reduce requires time_path")?;
+            let times = collect_page_values(json, time_path)?;
+            if times.len() != values.len() {
+                return Err(format!("Series has {} values but {} timestamps", values.len(), times.len()).into());
+            }
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+            Ok(time_weighted_average(&values, &times, now))
+        }
+    }
+}
 
-    // Check if price is fresh (within 120 seconds)
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    if now - publish_time > 120 {
-        return Err(format!("Pyth price is stale (published {} seconds ago)", now - publish_time).into());
+/// Average of samples weighted by how long each stood: until the next sample, the last one until `now`
+/// Timestamps above 1e11 are read as milliseconds; falls back to a simple average when no time elapsed
+fn time_weighted_average(values: &[f64], times: &[f64], now: f64) -> f64 {
+    let mut samples: Vec<(f64, f64)> = times.iter()
+        .map(|&t| if t > 1e11 { t / 1000.0 } else { t })
+        .zip(values.iter().cloned())
+        .collect();
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut weighted_sum = 0.0;
+    let mut total_weight = 0.0;
+    for (i, &(time, value)) in samples.iter().enumerate() {
+        let until = samples.get(i + 1).map_or(now, |next| next.0);
+        let weight = (until - time).max(0.0);
+        weighted_sum += value * weight;
+        total_weight += weight;
     }
 
-    // Calculate decimal price
-    let price = price_raw * 10f64.powi(expo as i32);
+    if total_weight > 0.0 {
+        weighted_sum / total_weight
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
 
-    Ok(SourcePrice {
-        source_name: "pyth".to_string(),
-        value: DataValue::Number(price),
-        timestamp: publish_time,
-    })
+/// Numeric values of one page at `path` (`*` segments expand arrays)
+fn collect_page_values(page: &Value, path: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    let mut nodes = Vec::new();
+    let parts: Vec<&str> = path.split('.').collect();
+    collect_json_nodes(page, &parts, path, &mut nodes)?;
+
+    nodes.into_iter()
+        .map(|node| match convert_json_value(node, path, &ValueType::Number)? {
+            DataValue::Number(n) => Ok(n),
+            _ => Err(format!("Value at '{}' is not a number", path).into()),
+        })
+        .collect()
 }
 
-/// Fetch price from custom user-defined source
-pub fn fetch_custom(config: &CustomSourceConfig) -> Result<SourcePrice, Box<dyn Error>> {
-    // Build HTTP request
-    let mut request = match config.method.to_uppercase().as_str() {
-        "GET" => Client::new().get(&config.url),
-        "POST" => {
-            let mut req = Client::new().post(&config.url);
-
-            // Add body if provided
-            if let Some(body) = &config.body {
-                let body_str = serde_json::to_string(body)?;
-                req = req.body(body_str.as_bytes());
-                // Auto-add Content-Type header if not already provided
-                if !config.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("content-type")) {
-                    req = req.header("Content-Type", "application/json");
-                }
+/// Walk a path where `*` matches every element of an array (e.g. "validators.*.stake")
+fn collect_json_nodes<'a>(
+    json: &'a Value,
+    parts: &[&str],
+    path: &str,
+    out: &mut Vec<&'a Value>,
+) -> Result<(), Box<dyn Error>> {
+    match parts.split_first() {
+        None => out.push(json),
+        Some((&"*", rest)) => {
+            let items = json.as_array()
+                .ok_or_else(|| format!("JSON path '{}': '*' applied to a non-array", path))?;
+            for item in items {
+                collect_json_nodes(item, rest, path, out)?;
             }
-
-            req
         }
-        _ => return Err(format!("Unsupported HTTP method: {}", config.method).into()),
-    };
-
-    // Add custom headers
-    for (key, value) in &config.headers {
-        request = request.header(key.as_str(), value.as_str());
+        Some((part, rest)) => collect_json_nodes(navigate_json_path(json, part)?, rest, path, out)?,
     }
+    Ok(())
+}
 
-    // Auto-add Authorization Bearer if API_KEY is in environment
-    if let Ok(api_key) = env::var("API_KEY") {
-        eprintln!("✓ API_KEY found, string length: {} characters", api_key.len());
-        let auth_header = format!("Bearer {}", api_key);
-        request = request.header("Authorization", auth_header.as_str());
+/// Next page URL from `next_path`; paths starting with "/" are resolved against the base URL's host
+fn next_page_url(page: &Value, next_path: &str, base_url: &str) -> Option<String> {
+    let next = navigate_json_path(page, next_path).ok()?.as_str()?;
+    if next.is_empty() {
+        return None;
     }
 
-    // Send request
-    let response = request
-        .connect_timeout(Duration::from_secs(10))
-        .send()?;
+    resolve_against(base_url, next)
+}
 
-    // Check status
-    let status = response.status();
-    if status < 200 || status >= 300 {
-        return Err(format!("HTTP {}", status).into());
+/// Size-weighted mid over the top K bid and ask levels of an order book
+/// Levels are `[price, size]` arrays (numbers or numeric strings)
+fn calculate_depth_mid(json: &Value, depth: &DepthConfig) -> Result<f64, Box<dyn Error>> {
+    if depth.levels == 0 {
+        return Err("depth.levels must be at least 1".into());
     }
 
-    // Parse JSON response
-    let body = response.body()?;
-    let json: Value = serde_json::from_slice(&body)?;
+    let bid = weighted_level_price(navigate_json_path(json, &depth.bids_path)?, depth.levels, "bids")?;
+    let ask = weighted_level_price(navigate_json_path(json, &depth.asks_path)?, depth.levels, "asks")?;
 
-    // Extract value using JSON path (e.g. "data.price" or "rates.USD")
-    let value = extract_json_value(&json, &config.json_path, &config.value_type)?;
+    Ok((bid + ask) / 2.0)
+}
 
-    // Get current timestamp
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)?
-        .as_secs();
+/// Size-weighted average price over the first `levels` entries of a book side
+fn weighted_level_price(side: &Value, levels: usize, label: &str) -> Result<f64, Box<dyn Error>> {
+    let entries = side.as_array().ok_or_else(|| format!("Depth {} is not an array", label))?;
+
+    let mut notional = 0.0;
+    let mut total_size = 0.0;
+    for level in entries.iter().take(levels) {
+        let price = level.get(0).and_then(value_as_f64);
+        let size = level.get(1).and_then(value_as_f64);
+        match (price, size) {
+            (Some(p), Some(s)) => {
+                notional += p * s;
+                total_size += s;
+            }
+            _ => return Err(format!("Depth {} level is not [price, size]", label).into()),
+        }
+    }
 
-    Ok(SourcePrice {
-        source_name: "custom".to_string(),
-        value,
-        timestamp,
-    })
+    if total_size <= 0.0 {
+        return Err(format!("Depth {} has no size", label).into());
+    }
+
+    Ok(notional / total_size)
+}
+
+/// Read a JSON number or numeric string as f64
+fn value_as_f64(value: &Value) -> Option<f64> {
+    value.as_f64().or_else(|| value.as_str().and_then(|s| s.parse::<f64>().ok()))
 }
 
 /// Extract value from JSON using dot notation path
-/// Examples: "price", "data.price", "rates.USD", "blocks.0.author_account_id"
+/// Examples: "price", "data.price", "rates.USD", "blocks.0.author_account_id", "ticks.last.p"
 fn extract_json_value(json: &Value, path: &str, value_type: &ValueType) -> Result<DataValue, Box<dyn Error>> {
+    let current = navigate_json_path(json, path)?;
+    convert_json_value(current, path, value_type)
+}
+
+/// Extract a numeric value, first removing `chars` from it when the source sent a string
+/// (thousands separators, currency symbols, e.g. "$1,234.56")
+fn extract_stripped_value(json: &Value, path: &str, value_type: &ValueType, chars: &str) -> Result<DataValue, Box<dyn Error>> {
+    let current = navigate_json_path(json, path)?;
+    match current.as_str() {
+        Some(text) => {
+            let stripped: String = text.chars().filter(|c| !chars.contains(*c)).collect();
+            convert_json_value(&Value::String(stripped), path, value_type)
+        }
+        None => convert_json_value(current, path, value_type),
+    }
+}
+
+/// Walk a dot notation path (object keys, array indexes, or `last`/negative indexes from the end)
+fn navigate_json_path<'a>(json: &'a Value, path: &str) -> Result<&'a Value, Box<dyn Error>> {
     let parts: Vec<&str> = path.split('.').collect();
     let mut current = json;
 
@@ -565,45 +2116,168 @@ fn extract_json_value(json: &Value, path: &str, value_type: &ValueType) -> Resul
             current = current
                 .get(index)
                 .ok_or_else(|| format!("JSON path '{}' array index '{}' out of bounds", path, part))?;
+        } else if let (Some(array), Some(back)) = (current.as_array(), index_from_end(part)) {
+            // Latest element of snapshot arrays, e.g. "ticks.last.p" or "ticks.-1.p"
+            current = array
+                .len()
+                .checked_sub(back)
+                .and_then(|index| array.get(index))
+                .ok_or_else(|| format!("JSON path '{}' array index '{}' out of bounds", path, part))?;
         } else {
             return Err(format!("JSON path '{}' not found at '{}'", path, part).into());
         }
     }
 
-    // Extract based on requested type
-    match value_type {
-        ValueType::Number => {
-            if let Some(num) = current.as_f64() {
-                Ok(DataValue::Number(num))
-            } else if let Some(s) = current.as_str() {
-                // Try to parse string as number
-                let num = s.parse::<f64>()
-                    .map_err(|e| format!("Failed to parse '{}' as number: {}", s, e))?;
-                Ok(DataValue::Number(num))
-            } else if let Some(i) = current.as_i64() {
-                Ok(DataValue::Number(i as f64))
-            } else if let Some(u) = current.as_u64() {
-                Ok(DataValue::Number(u as f64))
-            } else {
-                Err(format!("Value at '{}' is not a number", path).into())
-            }
-        }
-        ValueType::String => {
-            if let Some(s) = current.as_str() {
-                Ok(DataValue::Text(s.to_string()))
-            } else {
-                // Convert to string representation
-                Ok(DataValue::Text(current.to_string()))
-            }
-        }
-        ValueType::Boolean => {
-            if let Some(b) = current.as_bool() {
-                Ok(DataValue::Boolean(b))
-            } else {
-                Err(format!("Value at '{}' is not a boolean", path).into())
-            }
+    Ok(current)
+}
+
+/// Parse `last` or `-N` path segments into an offset from the end of an array (1 = last element)
+fn index_from_end(part: &str) -> Option<usize> {
+    if part == "last" {
+        return Some(1);
+    }
+    part.strip_prefix('-')
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|&n| n > 0)
+}
+
+/// Extract a value of the requested type from an already located JSON node
+fn convert_json_value(current: &Value, path: &str, value_type: &ValueType) -> Result<DataValue, Box<dyn Error>> {
+    // Typed construction: the declared value_type decides, not the JSON shape
+    DataValue::from_json(current, value_type)
+        .map_err(|e| format!("Value at '{}': {}", path, e).into())
+}
+
+/// Fetch a value from a NEAR contract view method (e.g. a token balance or a stored price)
+/// Note: view results carry no timestamp, so this is stamped with local time
+pub fn fetch_near_view(config: &NearViewConfig, id: &str, ctx: &mut FetchContext) -> Result<SourcePrice, Box<dyn Error>> {
+    check_url_policy(&config.rpc_url, ctx.config)?;
+
+    let body = near_view_request(config, id, "0").to_string();
+    let json = ctx.send_json("POST", &config.rpc_url, &[("Content-Type", "application/json")], Some(body.as_bytes()))?;
+    let value = parse_near_view_response(&json, config)?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(SourcePrice::new("near_view".to_string(), value, timestamp))
+}
+
+/// Run several view calls against one RPC endpoint as a single JSON-RPC batch (array body)
+/// Results are keyed by the given call keys; responses are matched by their JSON-RPC id
+pub fn fetch_near_view_batch(
+    rpc_url: &str,
+    calls: &[(String, &NearViewConfig, &str)],
+    ctx: &mut FetchContext,
+) -> Result<HashMap<String, Result<SourcePrice, String>>, Box<dyn Error>> {
+    check_url_policy(rpc_url, ctx.config)?;
+
+    let requests: Vec<Value> = calls.iter()
+        .enumerate()
+        .map(|(index, (_, config, id))| near_view_request(config, id, &index.to_string()))
+        .collect();
+    let body = Value::Array(requests).to_string();
+
+    let json = ctx.send_json("POST", rpc_url, &[("Content-Type", "application/json")], Some(body.as_bytes()))?;
+    let responses = json.as_array().ok_or("RPC does not support batch requests")?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    Ok(calls.iter()
+        .enumerate()
+        .map(|(index, (key, config, _))| {
+            let rpc_id = index.to_string();
+            let result = responses.iter()
+                .find(|r| r.get("id").and_then(|v| v.as_str()) == Some(rpc_id.as_str()))
+                .ok_or_else(|| "View call missing from batch response".to_string())
+                .and_then(|response| parse_near_view_response(response, config).map_err(|e| e.to_string()))
+                .map(|value| SourcePrice::new("near_view".to_string(), value, timestamp));
+            (key.clone(), result)
+        })
+        .collect())
+}
+
+/// Identity of a view call for batching and caching (one request id can drive several calls)
+pub fn near_view_call_key(config: &NearViewConfig, id: &str) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{:?}",
+        config.rpc_url,
+        config.contract,
+        config.method,
+        near_view_args(config, id),
+        config.json_path,
+        config.value_type
+    )
+}
+
+/// Method args with "{id}" substituted in every string (no args: `{}`)
+fn near_view_args(config: &NearViewConfig, id: &str) -> Value {
+    fn render(value: &Value, id: &str) -> Value {
+        match value {
+            Value::String(s) => Value::String(s.replace("{id}", id)),
+            Value::Array(items) => Value::Array(items.iter().map(|v| render(v, id)).collect()),
+            Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), render(v, id))).collect()),
+            other => other.clone(),
         }
     }
+
+    match &config.args {
+        Some(args) => render(args, id),
+        None => json!({}),
+    }
+}
+
+/// JSON-RPC `query` request for a `call_function` view call at final finality
+fn near_view_request(config: &NearViewConfig, id: &str, rpc_id: &str) -> Value {
+    let args = near_view_args(config, id).to_string();
+
+    json!({
+        "jsonrpc": "2.0",
+        "id": rpc_id,
+        "method": "query",
+        "params": {
+            "request_type": "call_function",
+            "finality": "final",
+            "account_id": config.contract,
+            "method_name": config.method,
+            "args_base64": base64::engine::general_purpose::STANDARD.encode(args),
+        },
+    })
+}
+
+/// Decode a view call response: `result.result` holds the returned JSON as a byte array
+fn parse_near_view_response(response: &Value, config: &NearViewConfig) -> Result<DataValue, Box<dyn Error>> {
+    // Transport/RPC errors: {"error": {"name": ..., "cause": {...}, "message": ...}}
+    if let Some(error) = response.get("error") {
+        let message = error.get("cause").and_then(|c| c.get("name")).and_then(|v| v.as_str())
+            .or_else(|| error.get("message").and_then(|v| v.as_str()))
+            .unwrap_or("unknown error");
+        return Err(format!("NEAR RPC error: {}", message).into());
+    }
+
+    let result = response.get("result").ok_or("Result not found")?;
+
+    // Contract panics and missing methods come back as a string in result.error
+    if let Some(error) = result.get("error").and_then(|v| v.as_str()) {
+        return Err(format!("View call failed: {}", error).into());
+    }
+
+    let bytes: Vec<u8> = result.get("result")
+        .and_then(|v| v.as_array())
+        .ok_or("View result bytes not found")?
+        .iter()
+        .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+        .collect::<Option<_>>()
+        .ok_or("View result is not a byte array")?;
+
+    let decoded: Value = serde_json::from_slice(&bytes)
+        .map_err(|e| format!("View result is not JSON: {}", e))?;
+
+    let current = if config.json_path.is_empty() {
+        &decoded
+    } else {
+        navigate_json_path(&decoded, &config.json_path)?
+    };
+
+    convert_json_value(current, &config.json_path, &config.value_type)
 }
 
 /// Get price fetcher function by source name
@@ -611,33 +2285,310 @@ pub fn fetch_price(
     source_name: &str,
     token_id: &str,
     api_key: Option<&str>,
+    source: &PriceSource,
+    ctx: &mut FetchContext,
 ) -> Result<SourcePrice, Box<dyn Error>> {
     match source_name {
-        "coingecko" => fetch_coingecko(token_id, api_key),
-        "coinmarketcap" => fetch_coinmarketcap(token_id, api_key),
-        "twelvedata" => fetch_twelvedata(token_id, api_key),
-        "exchangerate-api" => fetch_exchangerate_api(token_id, api_key),
-        "binance" => fetch_binance(token_id),
-        "huobi" => fetch_huobi(token_id),
-        "cryptocom" => fetch_cryptocom(token_id),
-        "kucoin" => fetch_kucoin(token_id),
-        "gate" => fetch_gate(token_id),
-        "pyth" => fetch_pyth(token_id),
+        "coingecko" if source.platform.is_some() => {
+            let platform = source.platform.as_deref().unwrap_or_default();
+            let quote = source.quote.as_deref().unwrap_or("usd").to_lowercase();
+            fetch_coingecko_token_price(platform, token_id, &quote, api_key, ctx)
+        }
+        "coingecko" => {
+            let quote = source.quote.as_deref().unwrap_or("usd").to_lowercase();
+            match source.id_kind {
+                IdKind::Symbol => {
+                    let coin_id = resolve_coingecko_symbol(token_id, api_key, ctx)?;
+                    fetch_coingecko(&coin_id, &quote, api_key, ctx)
+                }
+                IdKind::Id => fetch_coingecko(token_id, &quote, api_key, ctx),
+            }
+        }
+        "coinmarketcap" => {
+            let quote = source.quote.as_deref().unwrap_or("USD").to_uppercase();
+            fetch_coinmarketcap(token_id, &quote, api_key, ctx)
+        }
+        "twelvedata" => fetch_twelvedata(token_id, api_key, ctx),
+        "exchangerate-api" => fetch_exchangerate_api(token_id, api_key, ctx),
+        "binance" => match source.candles {
+            Some(candles) => {
+                let interval = source.candle_interval.as_deref().unwrap_or("1m");
+                fetch_binance_candles(token_id, candles, interval, ctx)
+            }
+            None if source.fast => fetch_binance(token_id, ctx),
+            None => fetch_binance_24h(token_id, ctx),
+        },
+        "huobi" => fetch_huobi(token_id, ctx),
+        "cryptocom" => fetch_cryptocom(token_id, ctx),
+        "kucoin" if source.fast => fetch_kucoin(token_id, ctx),
+        "kucoin" => fetch_kucoin_24h(token_id, ctx),
+        "gate" => fetch_gate(token_id, ctx),
+        "pyth" => fetch_pyth(token_id, &source.price_kind, ctx),
+        "deribit" => fetch_deribit(token_id, ctx),
+        "messari" => fetch_messari(token_id, api_key, ctx),
+        "bithumb" => fetch_bithumb(token_id, ctx),
+        "coinbase_rates" => fetch_coinbase_rate(token_id, ctx),
+        "chainlink_rest" => fetch_chainlink_rest(token_id, ctx),
+        "binance_imbalance" => fetch_binance_imbalance(token_id, ctx),
+        "okx_funding" => fetch_okx_funding(token_id, ctx),
+        "bybit_funding" => fetch_bybit_funding(token_id, ctx),
         _ => Err(format!("Unknown source: {}", source_name).into()),
     }
 }
 
+/// Sources whose API answers in one fixed currency, so a declared `quote` can only confirm it
+const FIXED_QUOTES: &[(&str, &str)] = &[("messari", "USD"), ("bithumb", "KRW")];
+
+/// Currency a source always answers in, regardless of `quote` (None: the quote is requested or
+/// named by the id, e.g. coingecko vs_currencies or a "BTCUSDT" pair)
+pub fn fixed_quote(source_name: &str) -> Option<&'static str> {
+    FIXED_QUOTES.iter().find(|(name, _)| *name == source_name).map(|(_, quote)| *quote)
+}
+
+/// No `quote`, or USD; batches are always fetched in USD
+fn is_default_quote(source: &PriceSource) -> bool {
+    source.quote.as_deref().is_none_or(|quote| quote.eq_ignore_ascii_case("usd"))
+}
+
+/// Stablecoin quote of an exchange symbol (e.g. "BTCUSDT", "btc_usdt" -> "USDT")
+pub fn stablecoin_quote(source_name: &str, symbol: &str) -> Option<&'static str> {
+    const EXCHANGES: [&str; 5] = ["binance", "huobi", "cryptocom", "kucoin", "gate"];
+    if !EXCHANGES.contains(&source_name) {
+        return None;
+    }
+
+    if symbol.to_uppercase().ends_with("USDT") {
+        Some("USDT")
+    } else {
+        None
+    }
+}
+
+/// Deployment policy for a source, checked before it is fetched or served from the cache
+pub fn check_source_policy(source: &PriceSource, config: &ExecutionConfig) -> Result<(), Box<dyn Error>> {
+    let source_name = config.resolve_source_name(&source.name);
+    if !config.is_source_allowed(source_name) {
+        return Err(format!("Source '{}' is not allowed by this deployment", source_name).into());
+    }
+
+    // wasi:http never exposes the peer certificate, so a pin can't be checked: fail closed
+    if let Some(pin) = &source.cert_pin {
+        return Err(format!(
+            "Certificate pin {} cannot be verified: the WASI HTTP client does not expose TLS certificates",
+            pin
+        ).into());
+    }
+
+    Ok(())
+}
+
 /// Fetch price with custom config support
 pub fn fetch_price_with_config(
-    source_name: &str,
+    source: &PriceSource,
     token_id: &str,
     api_key: Option<&str>,
-    custom_config: Option<&CustomSourceConfig>,
+    batch: &BatchResults,
+    config: &ExecutionConfig,
+    deadline: Option<Instant>,
 ) -> Result<SourcePrice, Box<dyn Error>> {
-    if source_name == "custom" {
-        let config = custom_config.ok_or("Custom source requires 'custom' config")?;
-        fetch_custom(config)
+    // Resolve user-defined aliases (e.g. "cg" -> "coingecko") before dispatch
+    let source_name = config.resolve_source_name(&source.name);
+
+    // Deployment policy: refuse disallowed sources before any network call
+    check_source_policy(source, config)?;
+
+    let mut ctx = FetchContext::new(config, source_name);
+    ctx.deadline = deadline;
+    // View calls are batched per call, not per request id
+    let batch_id = match &source.near_view {
+        Some(view) if source_name == "near_view" => near_view_call_key(view, token_id),
+        _ => token_id.to_string(),
+    };
+    let batched = batch.get(&(source_name.to_string(), batch_id))
+        .filter(|_| source.price_kind.is_spot() && source.platform.is_none() && is_default_quote(source));
+    let mut price = if let Some(result) = batched {
+        // Serve from a coalesced batch call if one already covered this id (batches hold spot prices)
+        result.clone()?
+    } else if source_name == "custom" {
+        let custom = source.custom.as_ref().ok_or("Custom source requires 'custom' config")?;
+        fetch_custom(custom, source.verify_signature.as_ref(), &mut ctx)?
+    } else if source_name == "near_view" {
+        let view = source.near_view.as_ref().ok_or("near_view source requires 'near_view' config")?;
+        fetch_near_view(view, token_id, &mut ctx)?
     } else {
-        fetch_price(source_name, token_id, api_key)
+        fetch_price(source_name, token_id, api_key, source, &mut ctx)?
+    };
+
+    // Declared quote wins; otherwise exchange pairs like BTCUSDT / BTC-USDT are quoted in USDT, not USD
+    if let Some(quote) = &source.quote {
+        let quote = quote.to_uppercase();
+        price.currency = if quote == "USD" { None } else { Some(quote) };
+    } else if price.currency.is_none() {
+        price.currency = stablecoin_quote(source_name, token_id).map(str::to_string);
+    }
+
+    // User label replaces the built-in name (e.g. several custom sources in one request)
+    if let Some(label) = &source.label {
+        price.source_name = label.clone();
+    }
+
+    // Attach diagnostics collected during the fetch
+    price.detail = ctx.detail;
+    price.detail.name = price.source_name.clone();
+    price.detail.currency = price.currency.clone();
+
+    Ok(price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom_config(value: Value) -> CustomSourceConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn gate_v4_falls_back_to_its_mirror_host() {
+        assert_eq!(
+            mirror_urls("https://api.gateio.ws/api/v4/spot/tickers?currency_pair=BTC_USDT"),
+            vec!["https://api.gate.io/api/v4/spot/tickers?currency_pair=BTC_USDT".to_string()]
+        );
+        assert_eq!(mirror_urls("https://api.binance.com/api/v3/ticker/price").len(), 5);
+        assert!(mirror_urls("https://data.gateapi.io/api2/1/ticker/btc_usdt").is_empty());
+    }
+
+    #[test]
+    fn post_snapshot_sends_body_and_reads_last_tick() {
+        let config = custom_config(json!({
+            "url": "https://example.com/snapshot",
+            "method": "POST",
+            "body": {"op": "subscribe", "channel": "ticker"},
+            "json_path": "ticks.last.p"
+        }));
+
+        let body = custom_request_body(&config, "POST").unwrap();
+        assert_eq!(body.as_deref(), Some(r#"{"channel":"ticker","op":"subscribe"}"#));
+        assert_eq!(custom_request_body(&config, "GET").unwrap(), None);
+
+        let response = json!({"ticks": [{"p": 123.4}]});
+        let value = extract_json_value(&response, &config.json_path, &config.value_type).unwrap();
+        assert_eq!(value.as_number(), Some(123.4));
+
+        let response = json!({"ticks": [{"p": 120.0}, {"p": 121.5}, {"p": 123.4}]});
+        assert_eq!(extract_json_value(&response, "ticks.last.p", &ValueType::Number).unwrap().as_number(), Some(123.4));
+        assert_eq!(extract_json_value(&response, "ticks.-2.p", &ValueType::Number).unwrap().as_number(), Some(121.5));
+        assert!(extract_json_value(&response, "ticks.-4.p", &ValueType::Number).is_err());
+    }
+
+    #[test]
+    fn quote_is_fixed_only_for_single_currency_apis() {
+        assert_eq!(fixed_quote("messari"), Some("USD"));
+        assert_eq!(fixed_quote("bithumb"), Some("KRW"));
+        assert_eq!(fixed_quote("coingecko"), None);
+
+        let source = |quote: Value| -> PriceSource { serde_json::from_value(json!({"name": "coingecko", "quote": quote})).unwrap() };
+        assert!(is_default_quote(&source(Value::Null)));
+        assert!(is_default_quote(&source(json!("USD"))));
+        assert!(!is_default_quote(&source(json!("eur"))));
+    }
+
+    #[test]
+    fn retry_sleep_never_passes_the_deadline() {
+        let delay = Duration::from_secs(10);
+        assert!(check_within_deadline(delay, None).is_ok());
+        assert!(check_within_deadline(delay, Some(Instant::now() + Duration::from_secs(60))).is_ok());
+
+        let err = check_within_deadline(delay, Some(Instant::now() + Duration::from_secs(2))).unwrap_err();
+        assert!(err.to_string().contains("would pass the deadline"), "{}", err);
+        assert!(check_within_deadline(Duration::from_millis(1), Some(Instant::now())).is_err());
+    }
+
+    #[test]
+    fn graphql_query_reads_data_and_reports_errors() {
+        let config = custom_config(json!({
+            "url": "https://example.com/subgraph",
+            "graphql": {"query": "query($id: ID!) { pool(id: $id) { tvl } }", "variables": {"id": "0xabc"}},
+            "json_path": "pool.tvl"
+        }));
+
+        let body: Value = serde_json::from_str(&custom_request_body(&config, "GET").unwrap().unwrap()).unwrap();
+        assert_eq!(body["variables"]["id"], "0xabc");
+
+        let response = json!({"data": {"pool": {"tvl": "1234.5"}}});
+        assert_eq!(graphql_error(&response), None);
+        let value = extract_json_value(&response["data"], &config.json_path, &config.value_type).unwrap();
+        assert_eq!(value.as_number(), Some(1234.5));
+
+        let response = json!({
+            "data": null,
+            "errors": [{"message": "pool not found"}, {"message": "rate limited"}]
+        });
+        assert_eq!(graphql_error(&response).as_deref(), Some("pool not found; rate limited"));
+        assert_eq!(graphql_error(&json!({"data": {}, "errors": []})), None);
+    }
+
+    #[test]
+    fn credential_headers_are_recognized_for_cross_host_redirects() {
+        for name in ["Authorization", "cookie", "X-CMC_PRO_API_KEY", "x-messari-api-key", "X-Api-Key"] {
+            assert!(is_credential_header(name), "{}", name);
+        }
+        for name in ["Accept", "Content-Type", "User-Agent"] {
+            assert!(!is_credential_header(name), "{}", name);
+        }
+
+        assert!(same_host("https://api.example.com/v1", "https://API.example.com/v2?x=1"));
+        assert!(!same_host("https://api.example.com/v1", "https://cdn.example.net/v1"));
+        assert!(!same_host("https://api.example.com/v1", "https://api.example.com:8443/v1"));
+
+        let mut headers = vec![("Accept", "application/json"), ("Authorization", "Bearer t"), ("x-messari-api-key", "k")];
+        headers.retain(|(name, _)| !is_credential_header(name));
+        assert_eq!(headers, vec![("Accept", "application/json")]);
+    }
+
+    #[test]
+    fn cmc_status_reports_api_errors() {
+        let response = json!({"status": {"error_code": 1001, "error_message": "This API Key is invalid."}});
+        assert_eq!(cmc_status_error(&response).as_deref(), Some("CoinMarketCap error 1001: This API Key is invalid."));
+
+        let response = json!({"status": {"error_code": "1008"}});
+        assert_eq!(cmc_status_error(&response).as_deref(), Some("CoinMarketCap error 1008: no error message"));
+
+        let response = json!({"status": {"error_code": 0, "error_message": null}, "data": {}});
+        assert_eq!(cmc_status_error(&response), None);
+    }
+
+    #[test]
+    fn strip_chars_removes_separators_and_currency_signs() {
+        let response = json!({"a": "1,234.56", "b": "$1234.56", "c": " $ 1,234.56 ", "d": 1234.56});
+        for path in ["a", "b", "c", "d"] {
+            let value = extract_stripped_value(&response, path, &ValueType::Number, ",$ ").unwrap();
+            assert_eq!(value.as_number(), Some(1234.56), "{}", path);
+        }
+        assert!(extract_stripped_value(&json!({"a": "1.234,56 EUR"}), "a", &ValueType::Number, ",$ ").is_err());
+    }
+
+    #[test]
+    fn series_reduce_averages_trades() {
+        let response = json!({"trades": [
+            {"p": 10.0, "t": 1_700_000_000},
+            {"p": 20.0, "t": 1_700_000_010},
+            {"p": 30.0, "t": 1_700_000_030},
+            {"p": 40.0, "t": 1_700_000_060},
+            {"p": 50.0, "t": 1_700_000_100}
+        ]});
+
+        let average: SeriesReduce = serde_json::from_value(json!({"method": "average"})).unwrap();
+        assert_eq!(reduce_series(&response, "trades.*.p", &average).unwrap(), 30.0);
+
+        // Weights are 10, 20, 30, 40 and 20 seconds (the last trade stands until now)
+        let values = collect_page_values(&response, "trades.*.p").unwrap();
+        let times = collect_page_values(&response, "trades.*.t").unwrap();
+        let twap = time_weighted_average(&values, &times, 1_700_000_120.0);
+        assert!((twap - 4000.0 / 120.0).abs() < 1e-9, "{}", twap);
+
+        // Millisecond timestamps give the same result
+        let millis: Vec<f64> = times.iter().map(|t| t * 1000.0).collect();
+        assert!((time_weighted_average(&values, &millis, 1_700_000_120.0) - twap).abs() < 1e-9);
     }
 }
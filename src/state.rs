@@ -0,0 +1,55 @@
+use crate::types::HistorySample;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+
+// Maximum number of samples kept per request id (oldest are dropped first)
+pub const MAX_HISTORY_SAMPLES: usize = 100;
+
+/// State persisted between invocations (JSON file in a WASI preopened directory)
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct OracleState {
+    /// Ring buffer of aggregated values per request id, oldest first
+    #[serde(default)]
+    pub history: HashMap<String, Vec<HistorySample>>,
+}
+
+impl OracleState {
+    /// Load state from file (missing file = empty state)
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(OracleState::default()),
+            Err(e) => Err(format!("Failed to read state file '{}': {}", path, e).into()),
+        }
+    }
+
+    /// Write state back to file
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let bytes = serde_json::to_vec(self)?;
+        fs::write(path, bytes)
+            .map_err(|e| format!("Failed to write state file '{}': {}", path, e))?;
+        Ok(())
+    }
+
+    /// Append an aggregated value, trimming the buffer to MAX_HISTORY_SAMPLES
+    pub fn record(&mut self, id: &str, timestamp: u64, value: f64) {
+        let samples = self.history.entry(id.to_string()).or_default();
+        samples.push(HistorySample { timestamp, value });
+
+        if samples.len() > MAX_HISTORY_SAMPLES {
+            let excess = samples.len() - MAX_HISTORY_SAMPLES;
+            samples.drain(..excess);
+        }
+    }
+
+    /// Get the last `count` samples for an id, oldest first
+    pub fn last_samples(&self, id: &str, count: usize) -> Vec<HistorySample> {
+        match self.history.get(id) {
+            Some(samples) => samples[samples.len().saturating_sub(count)..].to_vec(),
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+/// Parse an RFC 3339 / ISO 8601 UTC timestamp into unix seconds
+/// Accepts "2024-10-20T12:34:56Z", fractional seconds and numeric offsets ("+02:00")
+pub fn parse_rfc3339(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if s.len() < 19 {
+        return None;
+    }
+
+    let year: i64 = s.get(0..4)?.parse().ok()?;
+    let month: u32 = s.get(5..7)?.parse().ok()?;
+    let day: u32 = s.get(8..10)?.parse().ok()?;
+    let hour: i64 = s.get(11..13)?.parse().ok()?;
+    let minute: i64 = s.get(14..16)?.parse().ok()?;
+    let second: i64 = s.get(17..19)?.parse().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Skip fractional seconds, then read the zone designator
+    let rest = &s[19..];
+    let rest = match rest.strip_prefix('.') {
+        Some(frac) => frac.trim_start_matches(|c: char| c.is_ascii_digit()),
+        None => rest,
+    };
+
+    let offset_secs: i64 = match rest {
+        "Z" | "z" | "" => 0,
+        _ => {
+            let sign = match rest.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            let off_hours: i64 = rest.get(1..3)?.parse().ok()?;
+            let off_minutes: i64 = rest.get(4..6)?.parse().ok()?;
+            sign * (off_hours * 3600 + off_minutes * 60)
+        }
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset_secs;
+
+    u64::try_from(secs).ok()
+}
+
+/// Format unix seconds as RFC 3339 UTC, e.g. 1700000000 -> "2023-11-14T22:13:20Z"
+pub fn format_rfc3339(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60
+    )
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic Gregorian date for days since 1970-01-01 (inverse of days_from_civil)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_and_parses_round_trip() {
+        assert_eq!(format_rfc3339(1700000000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        // Leap day
+        assert_eq!(format_rfc3339(951782400), "2000-02-29T00:00:00Z");
+
+        assert_eq!(parse_rfc3339("2023-11-14T22:13:20Z"), Some(1700000000));
+        assert_eq!(parse_rfc3339("2023-11-15T00:13:20.123+02:00"), Some(1700000000));
+        assert_eq!(parse_rfc3339("2023-13-14T22:13:20Z"), None);
+    }
+}
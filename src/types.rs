@@ -1,15 +1,61 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 // Maximum number of tokens allowed per request
 pub const MAX_TOKENS_PER_REQUEST: usize = 10;
 
+// Upper bound on probe_iterations (keeps probing well below provider rate limits)
+pub const MAX_PROBE_ITERATIONS: usize = 5;
+
+// Cached source values older than this are refetched (unless cache_ttl_secs overrides it)
+pub const DEFAULT_CACHE_TTL_SECS: u64 = 60;
+
+// Sources used from a discovery list by default, and the hard cap
+pub const DEFAULT_MAX_DISCOVERED: usize = 10;
+pub const MAX_DISCOVERED_SOURCES: usize = 25;
+
+// Longest raw response body kept per source by capture_raw_for (bytes, after redaction)
+pub const MAX_RAW_CAPTURE_BYTES: usize = 512;
+
+// Gateway used to fetch ipfs:// custom source URLs
+pub const DEFAULT_IPFS_GATEWAY: &str = "https://ipfs.io/ipfs/";
+
+// Base backoff before retrying a 429 without Retry-After (doubles per retry)
+pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 500;
+
+// Relative spread below which sources count as equal in the deviation check
+pub const DEFAULT_DEVIATION_EPSILON: f64 = 1e-8;
+
+// Custom source header limits (unless max_custom_headers / max_header_bytes override them)
+pub const DEFAULT_MAX_CUSTOM_HEADERS: usize = 16;
+pub const DEFAULT_MAX_HEADER_BYTES: usize = 1024;
+
 /// Aggregation method for combining prices from multiple sources
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum AggregationMethod {
     Average,     // Arithmetic mean
     Median,      // Median value (protection against outliers)
+    Latest,      // Value of the source with the newest timestamp (ties: first in request order)
     WeightedAvg, // Weighted average (currently uses equal weights)
+    FreshnessWeighted, // Weighted by 1 / (age + 1), age relative to the freshest source
+    ReputationWeighted, // Weighted by per-source `weight`, else the built-in reputation table
+    InverseVarianceWeighted, // Weighted by 1 / confidence^2 (sources without confidence use a default)
+    RobustIrls, // Iteratively reweighted around the weighted median; outliers fade out smoothly
+    MaxMinusMin,  // Spread across sources (e.g. block height divergence); not a price
+    BoolAnd,      // Boolean: true only if all sources are true
+    BoolOr,       // Boolean: true if any source is true
+    BoolMajority, // Boolean: strict majority vote (ties fail)
+}
+
+impl AggregationMethod {
+    /// Whether this method aggregates boolean values
+    pub fn is_boolean(&self) -> bool {
+        matches!(
+            self,
+            AggregationMethod::BoolAnd | AggregationMethod::BoolOr | AggregationMethod::BoolMajority
+        )
+    }
 }
 
 /// Data source configuration
@@ -25,6 +71,135 @@ pub struct PriceSource {
     /// Custom source configuration (only for "custom" source)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub custom: Option<CustomSourceConfig>,
+
+    /// Contract view call (only for "near_view" source)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub near_view: Option<NearViewConfig>,
+
+    /// CoinGecko asset platform (e.g. "ethereum"); `id` is then a contract address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+
+    /// Which Pyth price to read: "spot" (default) or "ema" (exponential moving average)
+    #[serde(default, skip_serializing_if = "PriceKind::is_spot")]
+    pub price_kind: PriceKind,
+
+    /// How `id` should be interpreted: "id" (default) or "symbol" (coingecko resolves e.g. "BTC" -> "bitcoin")
+    #[serde(default, skip_serializing_if = "IdKind::is_default")]
+    pub id_kind: IdKind,
+
+    /// Use the lighter ticker endpoint without a server timestamp (binance, kucoin; default: false)
+    /// By default these use 24h endpoints so timestamps reflect the exchange, not fetch time
+    #[serde(default)]
+    pub fast: bool,
+
+    /// Average the closes of the last N candles instead of the spot price (binance only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candles: Option<u32>,
+
+    /// Candle interval used with `candles` (default: "1m")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candle_interval: Option<String>,
+
+    /// Weight for reputation_weighted aggregation (default: built-in reputation table)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+
+    /// Name reported in `sources` and messages instead of the built-in name (e.g. "my_rpc")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// Currency this source's value is quoted in (e.g. "USDT", "USD"); default: inferred from the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote: Option<String>,
+
+    /// Type this source must return ("number", "integer", "string", "boolean"); any other value is dropped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<ValueType>,
+
+    /// Unit conversion factor applied to the fetched value (e.g. 1/31.1035 for per-ounce to per-gram)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_convert: Option<f64>,
+
+    /// SHA-256 certificate/public-key pin; unverifiable under wasi:http, so a pinned source always fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cert_pin: Option<String>,
+
+    /// Require a valid ed25519 signature from an allowed key in the response (custom sources only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_signature: Option<SigConfig>,
+
+    /// Watch the aggregate instead of contributing to it; disagreement adds a warning (default: false)
+    #[serde(default)]
+    pub canary: bool,
+}
+
+impl PriceSource {
+    /// Plain built-in source with an explicit id
+    pub fn builtin(name: &str, id: &str) -> Self {
+        PriceSource {
+            name: name.to_string(),
+            id: Some(id.to_string()),
+            custom: None,
+            near_view: None,
+            id_kind: IdKind::Id,
+            platform: None,
+            price_kind: PriceKind::Spot,
+            fast: false,
+            candles: None,
+            candle_interval: None,
+            weight: None,
+            label: None,
+            quote: None,
+            value_type: None,
+            unit_convert: None,
+            cert_pin: None,
+            verify_signature: None,
+            canary: false,
+        }
+    }
+}
+
+/// Kind of price read from sources that publish several (Pyth)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceKind {
+    /// Latest aggregate price (`price`)
+    #[default]
+    Spot,
+    /// Exponential moving average (`ema_price`), smoother and harder to manipulate
+    Ema,
+}
+
+impl PriceKind {
+    pub fn is_spot(&self) -> bool {
+        *self == PriceKind::Spot
+    }
+
+    /// Field of a Pyth `parsed` entry holding this price
+    pub fn pyth_field(&self) -> &'static str {
+        match self {
+            PriceKind::Spot => "price",
+            PriceKind::Ema => "ema_price",
+        }
+    }
+}
+
+/// Kind of source-specific identifier
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdKind {
+    /// Native source id (e.g. "bitcoin" for CoinGecko)
+    #[default]
+    Id,
+    /// Ticker symbol resolved to the native id (e.g. "BTC")
+    Symbol,
+}
+
+impl IdKind {
+    fn is_default(&self) -> bool {
+        *self == IdKind::Id
+    }
 }
 
 /// Value type for custom sources
@@ -34,6 +209,7 @@ pub enum ValueType {
     Number,  // f64
     String,  // String (stored in separate field)
     Boolean, // bool (converted to 1.0/0.0 for aggregation)
+    Integer, // u64 (decimal or "0x" hex, e.g. block heights), stored as f64
 }
 
 impl Default for ValueType {
@@ -49,6 +225,8 @@ pub struct CustomSourceConfig {
     pub url: String,
 
     /// JSON path to extract value (dot notation, e.g. "data.price" or "rates.USD")
+    /// Not needed when `depth` is set
+    #[serde(default)]
     pub json_path: String,
 
     /// Type of value to extract (default: number)
@@ -67,6 +245,206 @@ pub struct CustomSourceConfig {
     /// Example: {"method": "eth_getBalance", "params": ["0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045"]}
     #[serde(skip_serializing_if = "Option::is_none")]
     pub body: Option<serde_json::Value>,
+
+    /// JSON path to the value's confidence/uncertainty (used by inverse_variance_weighted)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence_path: Option<String>,
+
+    /// Compute a size-weighted mid from order book levels instead of reading `json_path`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<DepthConfig>,
+
+    /// Replace invalid UTF-8 bytes with U+FFFD instead of failing (string value_type only)
+    #[serde(default)]
+    pub lossy_decode: bool,
+
+    /// Extra HTTP status codes treated as success (e.g. 422 for "no data"); 2xx is always accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accept_status: Option<Vec<u16>>,
+
+    /// `[json_path, value]` that must match for the response to count as a success,
+    /// e.g. `["result", "true"]`; scalars compare by text, so "true" also matches true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success_when: Option<(String, serde_json::Value)>,
+
+    /// Characters removed from a string value before it is parsed as a number,
+    /// e.g. ",$ " turns "$1,234.56" into 1234.56 (number/integer value_type only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_chars: Option<String>,
+
+    /// Follow `next` links and reduce values collected across pages (json_path may use `*`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paginate: Option<Paginate>,
+
+    /// Reduce a short series (e.g. recent trades at "trades.*.price") to one value before aggregation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_reduce: Option<SeriesReduce>,
+
+    /// Send a GraphQL query instead of `body`; `json_path` is then relative to `data`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graphql: Option<GraphqlConfig>,
+}
+
+/// Order book depth configuration for custom sources
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DepthConfig {
+    /// JSON path to the bids array of `[price, size]` levels (e.g. "bids" or "result.b")
+    pub bids_path: String,
+
+    /// JSON path to the asks array of `[price, size]` levels
+    pub asks_path: String,
+
+    /// Number of top levels per side to include
+    pub levels: usize,
+}
+
+/// NEAR contract view call (`query` / `call_function` over JSON-RPC)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NearViewConfig {
+    /// JSON-RPC endpoint (default: NEAR mainnet public RPC)
+    #[serde(default = "default_near_rpc_url")]
+    pub rpc_url: String,
+
+    /// Contract account id (e.g. "wrap.near")
+    pub contract: String,
+
+    /// View method name (e.g. "ft_balance_of")
+    pub method: String,
+
+    /// Method arguments as JSON; "{id}" inside any string is replaced with the source id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<serde_json::Value>,
+
+    /// Path into the decoded result (empty: the whole result, e.g. a bare U128 string)
+    #[serde(default)]
+    pub json_path: String,
+
+    /// Expected value type
+    #[serde(default)]
+    pub value_type: ValueType,
+}
+
+fn default_near_rpc_url() -> String {
+    "https://rpc.mainnet.near.org".to_string()
+}
+
+/// GraphQL query for custom sources (e.g. The Graph subgraphs)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GraphqlConfig {
+    /// Query document, e.g. "query($id: ID!) { pool(id: $id) { totalValueLockedUSD } }"
+    pub query: String,
+
+    /// Query variables (default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variables: Option<serde_json::Value>,
+}
+
+/// Two-phase source list: a directory lists ids, each becomes a copy of `template`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DiscoveryConfig {
+    /// Directory endpoint; `json_path` selects the array of ids (strings or numbers)
+    pub directory: CustomSourceConfig,
+
+    /// Source fetched per discovered id; "{id}" in a custom url or json_path is replaced
+    pub template: PriceSource,
+
+    /// Max discovered sources to fetch (default: 10, capped at 25); the rest are ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_sources: Option<usize>,
+}
+
+impl DiscoveryConfig {
+    /// The template instantiated for one discovered id, labelled "<label or name>:<id>"
+    pub fn source_for(&self, id: &str) -> PriceSource {
+        let mut source = self.template.clone();
+        if let Some(custom) = &mut source.custom {
+            custom.url = custom.url.replace("{id}", id);
+            custom.json_path = custom.json_path.replace("{id}", id);
+        }
+        source.label = Some(format!("{}:{}", self.template.label.as_ref().unwrap_or(&self.template.name), id));
+        source.id = Some(id.to_string());
+        source
+    }
+}
+
+/// Where a signed source response keeps its signature, signer and signed message
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SigConfig {
+    /// JSON path to the ed25519 signature (hex or base64)
+    pub signature_path: String,
+
+    /// JSON path to the signer public key (hex, base64, or "ed25519:<base58>")
+    pub pubkey_path: String,
+
+    /// JSON path to the signed message: a string is signed as its UTF-8 bytes, anything else as
+    /// compact JSON (default: the value at `json_path`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_path: Option<String>,
+
+    /// Public keys whose signatures are accepted (same encodings as the signer key)
+    pub allowed_pubkeys: Vec<String>,
+}
+
+/// Pagination for custom sources that split results across pages
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Paginate {
+    /// JSON path to the next page URL (absolute, or a path on the same host); missing/null ends paging
+    pub next_path: String,
+
+    /// Maximum number of pages to fetch, including the first (default: 10)
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+
+    /// Maximum total response bytes across all pages (default: 1 MiB)
+    #[serde(default = "default_max_total_bytes")]
+    pub max_total_bytes: usize,
+
+    /// How collected values are reduced to one number (default: sum)
+    #[serde(default)]
+    pub reduce: PageReduce,
+}
+
+/// Reduction applied to values collected across pages
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PageReduce {
+    #[default]
+    Sum,
+    Average,
+    Min,
+    Max,
+    Count,
+}
+
+/// Per-source reduction of a series extracted with a `*` json_path
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SeriesReduce {
+    /// How the samples are combined (default: average)
+    #[serde(default)]
+    pub method: SeriesMethod,
+
+    /// Path to each sample's unix timestamp, with the same `*` expansion as json_path
+    /// (seconds, or milliseconds above 1e11); required for time_weighted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_path: Option<String>,
+}
+
+/// Reduction applied to a series of samples from one response
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeriesMethod {
+    #[default]
+    Average,
+    /// Each sample weighted by how long it stood: until the next sample, the last one until now
+    TimeWeighted,
+}
+
+fn default_max_pages() -> usize {
+    10
+}
+
+fn default_max_total_bytes() -> usize {
+    1024 * 1024
 }
 
 fn default_http_method() -> String {
@@ -79,7 +457,8 @@ pub struct DataRequest {
     /// Request identifier (e.g., "near_price", "eur_usd_rate", "block_validator")
     pub id: String,
 
-    /// List of data sources to query
+    /// List of data sources to query (not needed in history mode)
+    #[serde(default)]
     pub sources: Vec<PriceSource>,
 
     /// Method to aggregate values from multiple sources (default: average)
@@ -89,6 +468,262 @@ pub struct DataRequest {
     /// Minimum number of sources that must respond successfully (default: 1)
     #[serde(default = "default_min_sources")]
     pub min_sources_num: usize,
+
+    /// Flag numeric values that are bit-identical across all sources (default: false)
+    /// Independent sources rarely agree to the last bit - usually a shared upstream or caching proxy
+    #[serde(default)]
+    pub detect_identical: bool,
+
+    /// Fail the request instead of warning when identical values are detected (default: false)
+    #[serde(default)]
+    pub suspicious_identical_is_error: bool,
+
+    /// Accept a result backed by a single source without comment (default: true)
+    /// When false, single-source results are marked `degraded` with a warning
+    #[serde(default = "default_true")]
+    pub single_source_ok: bool,
+
+    /// Fail instead of degrading when only one source backs the result (requires single_source_ok: false)
+    #[serde(default)]
+    pub single_source_is_error: bool,
+
+    /// Minimum number of sources that must agree within `agreement_percent` (default: no consensus check)
+    /// Only the agreeing cluster is aggregated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_agree_sources: Option<usize>,
+
+    /// Max spread (percentage) between sources counted as agreeing (default: max_price_deviation_percent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agreement_percent: Option<f64>,
+
+    /// Max % a canary source may differ from the aggregate (default: the request's max deviation)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary_max_deviation_percent: Option<f64>,
+
+    /// Fail the request instead of warning when a canary disagrees (default: false)
+    #[serde(default)]
+    pub canary_is_error: bool,
+
+    /// Check the spread between sources against max_deviation_percent (default: true)
+    /// Turn off for non-price numerics (block heights, counts) where a large spread is legitimate
+    #[serde(default = "default_true")]
+    pub check_deviation: bool,
+
+    /// Max % deviation for this request only (default: the global max_price_deviation_percent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_deviation_percent: Option<f64>,
+
+    /// What to do when the deviation limit is exceeded (default: fail)
+    #[serde(default)]
+    pub on_deviation: OnDeviation,
+
+    /// Also require each source to be within a limit of the final aggregate (default: off)
+    #[serde(default)]
+    pub strict_deviation: StrictDeviation,
+
+    /// Per-source limit (% of the aggregate) for strict_deviation (default: the request's max deviation)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_deviation_percent: Option<f64>,
+
+    /// Flag numeric sources with fewer significant digits than this (likely rounded/cached data)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_significant_digits: Option<usize>,
+
+    /// Drop low-precision sources instead of only warning (default: false)
+    #[serde(default)]
+    pub drop_low_precision: bool,
+
+    /// Fee/slippage in basis points applied to the aggregate, returned as `adjusted_value`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_bps: Option<f64>,
+
+    /// Direction of the fee adjustment (default: sell, i.e. subtract)
+    #[serde(default)]
+    pub fee_side: FeeSide,
+
+    /// Fail if the aggregated value is below this bound
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<f64>,
+
+    /// Fail if the aggregated value is above this bound
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<f64>,
+
+    /// Per-source acceptance checks (freshness, bounds); failing sources are dropped before aggregation
+    #[serde(default)]
+    pub source_gates: SourceGates,
+
+    /// Last known good value supplied by the caller (e.g. the on-chain price), used by the clamp below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_price: Option<f64>,
+
+    /// Clamp the aggregate to within this % of `reference_price` and mark it degraded (instead of failing)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_change_percent_from_reference: Option<f64>,
+
+    /// Report a `depegged` output when the aggregate is more than this % away from `peg`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depeg_threshold_percent: Option<f64>,
+
+    /// Target value of a pegged asset for the depeg check (default: 1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peg: Option<f64>,
+
+    /// Discrete freshness tiers `[max_age_secs, weight]`, ascending by age, e.g. [[30, 1.0], [90, 0.5]]
+    /// A source takes the weight of the first tier its age fits in; older than the last tier it is dropped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub staleness_tiers: Vec<(u64, f64)>,
+
+    /// Method used instead of `median` when the two middle values of an even count differ by more
+    /// than the allowed deviation (default: keep the median; the ambiguity is reported either way)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub median_ambiguity_fallback: Option<AggregationMethod>,
+
+    /// Aggregate in 18-decimal fixed point instead of f64 (average, median, max_minus_min)
+    #[serde(default)]
+    pub use_decimal: bool,
+
+    /// Fail when the aggregate is below the last value stored for this id (block heights, counters)
+    /// Requires config.state_path
+    #[serde(default)]
+    pub monotonic: bool,
+
+    /// What to do with a value below the last stored one (default: reject)
+    #[serde(default)]
+    pub monotonic_action: MonotonicAction,
+
+    /// Fetch the source list from a directory endpoint first, then each discovered source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery: Option<DiscoveryConfig>,
+
+    /// Multiply the aggregate by this shared rate (an id in config.shared_rates), e.g. USD -> EUR
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convert_rate: Option<String>,
+
+    /// Named outputs with their own sources (e.g. "price" and "status"), used instead of `sources`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<NamedOutput>,
+}
+
+impl DataRequest {
+    /// Standalone request for one named output, inheriting this request's id and checks
+    pub fn output_request(&self, output: &NamedOutput) -> DataRequest {
+        DataRequest {
+            sources: output.sources.clone(),
+            aggregation_method: output.aggregation_method.clone(),
+            min_sources_num: output.min_sources_num,
+            outputs: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Deviation limit for this request: its own override, else the batch-wide limit
+    pub fn max_deviation(&self, global: f64) -> f64 {
+        self.max_deviation_percent.unwrap_or(global)
+    }
+}
+
+/// Checks every source value must pass to contribute (default: accept everything)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SourceGates {
+    /// Drop sources whose reported timestamp is older than this many seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_secs: Option<u64>,
+
+    /// Drop numeric sources below this value (e.g. a zero or negative price)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<f64>,
+
+    /// Drop numeric sources above this value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_value: Option<f64>,
+}
+
+/// One field of a multi-output request
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NamedOutput {
+    /// Output name, unique within the request (e.g. "price", "status")
+    pub name: String,
+
+    /// Sources queried for this output only
+    pub sources: Vec<PriceSource>,
+
+    /// Aggregation for this output (default: average)
+    #[serde(default = "default_aggregation_method")]
+    pub aggregation_method: AggregationMethod,
+
+    /// Minimum number of sources that must respond for this output (default: 1)
+    #[serde(default = "default_min_sources")]
+    pub min_sources_num: usize,
+
+    /// Expected type of the aggregated value; a mismatch fails this output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<ValueType>,
+}
+
+/// Per-source conformance check against the aggregate
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrictDeviation {
+    /// Only the min/max spread check applies
+    #[default]
+    Off,
+    /// Fail the request if any source is too far from the aggregate
+    Fail,
+    /// Drop sources too far from the aggregate and aggregate again
+    Drop,
+}
+
+/// Side of a trade the fee adjustment models
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSide {
+    /// Buyer pays the fee: price * (1 + fee)
+    Buy,
+    /// Seller receives less: price * (1 - fee)
+    #[default]
+    Sell,
+}
+
+/// Policy applied when sources deviate more than allowed
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDeviation {
+    /// Fail the request
+    #[default]
+    Fail,
+    /// Ignore the deviation and return the median as a robust estimate
+    UseMedian,
+    /// Drop the source farthest from the median and re-check once
+    DropOutliersThenRetry,
+}
+
+/// What to do when several requests in one batch share an id
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateIds {
+    /// Process all of them and add a warning to each affected response
+    #[default]
+    Warn,
+    /// Fail every request sharing the id without fetching
+    Reject,
+    /// Rename the second and later occurrences in the response ("id#2", "id#3", ...)
+    Suffix,
+}
+
+/// What to do with a request whose sources were cut off by `total_deadline_secs`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeoutBehavior {
+    /// Fail the request
+    #[default]
+    FailAll,
+    /// Aggregate whoever responded (if `min_sources_num` is met) and mark the result `degraded`
+    AggregatePartial,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 fn default_aggregation_method() -> AggregationMethod {
@@ -99,17 +734,254 @@ fn default_min_sources() -> usize {
     1
 }
 
+/// Execution-wide settings shared by all data requests (every field is optional)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ExecutionConfig {
+    /// Friendly source names mapped to canonical ones (e.g. "cg" -> "coingecko")
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+
+    /// Add `contributions_merkle_root` over the contributing sources' values (default: false)
+    #[serde(default)]
+    pub merkle_contributions: bool,
+
+    /// Record response size and parse time per source in `sources_detail` (default: false)
+    #[serde(default)]
+    pub perf_detail: bool,
+
+    /// Sources (resolved names) whose raw response body is kept in `sources_detail`, redacted and
+    /// truncated to 512 bytes (default: none)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capture_raw_for: Vec<String>,
+
+    /// Max headers per custom source (default: 16)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_custom_headers: Option<usize>,
+
+    /// Max bytes of name + value per custom source header (default: 1024)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_header_bytes: Option<usize>,
+
+    /// Only these sources may be used (resolved names, e.g. ["binance", "coingecko"]); default: all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_sources: Option<Vec<String>>,
+
+    /// Sources that may never be used (e.g. ["custom"]); checked after `allowed_sources`
+    #[serde(default)]
+    pub denied_sources: Vec<String>,
+
+    /// Relative tolerance below which the source spread counts as zero deviation (default: 1e-8)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deviation_epsilon: Option<f64>,
+
+    /// Follow HTTP 3xx redirects up to 5 hops (default: true); when false a redirect fails with its target
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follow_redirects: Option<bool>,
+
+    /// Add `timestamp_iso` (RFC 3339, UTC) next to each unix timestamp (off by default to keep payloads small)
+    #[serde(default)]
+    pub include_iso_timestamp: bool,
+
+    /// Indent the output JSON for reading (default: compact; the signature always covers the compact form)
+    #[serde(default)]
+    pub pretty_output: bool,
+
+    /// Retries after HTTP 429 per request (default: 0, no retry)
+    #[serde(default)]
+    pub max_retries: u32,
+
+    /// Base delay in ms for exponential backoff when a 429 has no Retry-After header (default: 500)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_backoff_ms: Option<u64>,
+
+    /// Include each source's final request URLs (secrets redacted) in `sources_detail` (default: false)
+    #[serde(default)]
+    pub debug_urls: bool,
+
+    /// Always return per-source values and timestamps in `sources_detail`, even for single-source or text results (default: false)
+    #[serde(default)]
+    pub always_include_sources: bool,
+
+    /// Echo the settings actually applied, defaults filled in, as `OracleResponse.effective_config` (default: false)
+    #[serde(default)]
+    pub echo_effective_config: bool,
+
+    /// Include a SHA-256 of the raw input in `OracleResponse.request_hash` (default: false)
+    #[serde(default)]
+    pub echo_request: bool,
+
+    /// Path to the state file (must be inside a WASI preopened directory)
+    /// When set, every successful numeric aggregate is appended to its history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_path: Option<String>,
+
+    /// Allow plain http:// custom source URLs (default: false, https only)
+    #[serde(default)]
+    pub allow_insecure_urls: bool,
+
+    /// Hosts allowed for custom sources even if local/private (e.g. internal RPC nodes)
+    #[serde(default)]
+    pub url_allowlist: Vec<String>,
+
+    /// Gateway prefix for ipfs:// custom source URLs (default: "https://ipfs.io/ipfs/")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipfs_gateway: Option<String>,
+
+    /// Source name -> base URL replacing the built-in scheme and host (e.g. a mock server or proxy)
+    #[serde(default)]
+    pub base_url_overrides: HashMap<String, String>,
+
+    /// Sort inputs before reduction and round aggregates to 8 decimals so replicas agree bit-for-bit
+    #[serde(default)]
+    pub deterministic: bool,
+
+    /// Overall time budget in seconds; sources not started by then are skipped (default: none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_deadline_secs: Option<u64>,
+
+    /// Delay in milliseconds between launching successive source fetches of a request (default: none)
+    /// (no concurrency cap exists: wasi-http-client calls block, so this runtime never has more than one in flight)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stagger_ms: Option<u64>,
+
+    /// Behavior for requests cut off by `total_deadline_secs` (default: fail_all)
+    #[serde(default)]
+    pub timeout_behavior: TimeoutBehavior,
+
+    /// Convert USDT-quoted exchange prices to USD before aggregation (default: false)
+    #[serde(default)]
+    pub normalize_stablecoins: bool,
+
+    /// Source for the USDT/USD rate used by `normalize_stablecoins` (default: coingecko "tether")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stablecoin_rate_source: Option<PriceSource>,
+
+    /// Cross-rates resolved once before any request (e.g. id "eur_usd"), used via `convert_rate`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shared_rates: Vec<DataRequest>,
+
+    /// Handling of requests that share an id (default: warn)
+    #[serde(default)]
+    pub duplicate_ids: DuplicateIds,
+
+    /// Path to the per-source response cache (must be inside a WASI preopened directory)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_path: Option<String>,
+
+    /// Max age in seconds of a cached source value (default: 60)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl_secs: Option<u64>,
+}
+
+impl ExecutionConfig {
+    /// Whether any option requires the per-source `sources_detail` block
+    pub fn wants_sources_detail(&self) -> bool {
+        self.perf_detail || self.always_include_sources || self.debug_urls || !self.capture_raw_for.is_empty()
+    }
+
+    /// Whether the allow/deny lists permit a (resolved) source name
+    pub fn is_source_allowed(&self, source_name: &str) -> bool {
+        let allowed = self.allowed_sources.as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|s| s == source_name));
+        allowed && !self.denied_sources.iter().any(|s| s == source_name)
+    }
+
+    /// Resolve a source name through the alias table (unknown names pass through)
+    pub fn resolve_source_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map(|s| s.as_str()).unwrap_or(name)
+    }
+}
+
+/// Execution mode
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Fetch sources and aggregate (default)
+    #[default]
+    Fetch,
+    /// Return stored history from the state file without any network calls
+    History,
+    /// Call each source a few times and report latency without aggregating
+    Probe,
+}
+
 /// Main request structure
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OracleRequest {
+    /// Execution mode (default: fetch)
+    #[serde(default)]
+    pub mode: Mode,
+
+    /// Number of samples per id returned in history mode (default: 10, capped by the buffer size)
+    #[serde(default = "default_history_count")]
+    pub history_count: usize,
+
+    /// Calls per source in probe mode (default: 3, max: MAX_PROBE_ITERATIONS)
+    #[serde(default = "default_probe_iterations")]
+    pub probe_iterations: usize,
+
     /// List of data requests
     pub requests: Vec<DataRequest>,
 
     /// Maximum allowed price deviation between sources (percentage)
     pub max_price_deviation_percent: f64,
+
+    /// Require every result to be close to the batch start time (consistent multi-asset snapshot)
+    #[serde(default)]
+    pub snapshot: bool,
+
+    /// Max distance in seconds between a result's timestamp and the batch start (default: 60)
+    #[serde(default = "default_snapshot_skew_secs")]
+    pub snapshot_skew_secs: u64,
+
+    /// What to do with a result outside the snapshot window (default: reject)
+    #[serde(default)]
+    pub snapshot_skew_action: SkewAction,
+
+    /// Execution settings (default: all off)
+    #[serde(default)]
+    pub config: ExecutionConfig,
+}
+
+fn default_history_count() -> usize {
+    10
+}
+
+fn default_probe_iterations() -> usize {
+    3
+}
+
+fn default_snapshot_skew_secs() -> u64 {
+    60
+}
+
+/// Handling of snapshot results whose timestamp is too far from the batch start
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkewAction {
+    /// Fail the result
+    #[default]
+    Reject,
+    /// Keep the result but mark it `degraded`
+    Degrade,
+}
+
+/// Handling of monotonic results below the last stored value
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonotonicAction {
+    /// Fail the result
+    #[default]
+    Reject,
+    /// Keep the result but mark it `degraded`
+    Degrade,
 }
 
 /// Data value type - can be number, text, or boolean
+///
+/// Untagged: deserializing picks the variant from the JSON shape alone, so the numeric string
+/// "123" becomes Text and a NaN (serialized as null) fails. Values from sources are therefore
+/// built with `DataValue::from_json` and the declared `ValueType`, never by deserializing.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum DataValue {
@@ -119,6 +991,62 @@ pub enum DataValue {
 }
 
 impl DataValue {
+    /// Build a value of the declared type from raw JSON, coercing rather than guessing by shape
+    /// e.g. "123" as Number -> Number(123.0), 123 as String -> Text("123"), "0x1f" as Integer -> Number(31.0)
+    pub fn from_json(value: &serde_json::Value, value_type: &ValueType) -> Result<DataValue, String> {
+        match value_type {
+            ValueType::Number => {
+                if let Some(num) = value.as_f64() {
+                    Ok(DataValue::Number(num))
+                } else if let Some(s) = value.as_str() {
+                    // Numbers as strings are common (exchange tickers, U128 balances);
+                    // "NaN", "inf" or an overflowing exponent would poison every comparison downstream
+                    match s.trim().parse::<f64>() {
+                        Ok(num) if num.is_finite() => Ok(DataValue::Number(num)),
+                        Ok(_) => Err(format!("'{}' is not a finite number", s)),
+                        Err(e) => Err(format!("failed to parse '{}' as number: {}", s, e)),
+                    }
+                } else {
+                    Err(format!("expected a number, got {}", value))
+                }
+            }
+            ValueType::String => match value.as_str() {
+                Some(s) => Ok(DataValue::Text(s.to_string())),
+                // Convert to string representation
+                None => Ok(DataValue::Text(value.to_string())),
+            },
+            ValueType::Boolean => value.as_bool()
+                .map(DataValue::Boolean)
+                .ok_or_else(|| format!("expected a boolean, got {}", value)),
+            ValueType::Integer => {
+                // JSON-RPC nodes often return heights as hex strings (e.g. eth_blockNumber "0x12a05f2")
+                let int = if let Some(u) = value.as_u64() {
+                    u
+                } else if let Some(s) = value.as_str() {
+                    match s.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16)
+                            .map_err(|e| format!("failed to parse '{}' as hex integer: {}", s, e))?,
+                        None => s.parse::<u64>()
+                            .map_err(|e| format!("failed to parse '{}' as integer: {}", s, e))?,
+                    }
+                } else {
+                    return Err(format!("expected an integer, got {}", value));
+                };
+                Ok(DataValue::Number(int as f64))
+            }
+        }
+    }
+
+    /// Whether the value has the shape produced by a source of this type
+    pub fn is_type(&self, value_type: &ValueType) -> bool {
+        matches!(
+            (self, value_type),
+            (DataValue::Number(_), ValueType::Number | ValueType::Integer)
+                | (DataValue::Text(_), ValueType::String)
+                | (DataValue::Boolean(_), ValueType::Boolean)
+        )
+    }
+
     /// Get numeric value (for aggregation)
     pub fn as_number(&self) -> Option<f64> {
         match self {
@@ -138,8 +1066,170 @@ pub struct PriceData {
     /// Unix timestamp when the data was fetched
     pub timestamp: u64,
 
+    /// `timestamp` as UTC RFC 3339 (only when include_iso_timestamp is set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_iso: Option<String>,
+
     /// List of sources that successfully returned data
     pub sources: Vec<String>,
+
+    /// Combined confidence of the aggregate (inverse_variance_weighted only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+
+    /// Aggregate adjusted by `fee_bps` in the `fee_side` direction (only when fee_bps is set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adjusted_value: Option<DataValue>,
+
+    /// (max - min) / median * 100 over contributing sources (numeric multi-source results only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread_percent: Option<f64>,
+
+    /// Source whose value became the aggregate (odd-count median, text values); None for blends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selected_source: Option<String>,
+
+    /// Per-source diagnostics (only when requested via execution config)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources_detail: Option<Vec<SourceDetail>>,
+
+    /// Sources that formed the accepted consensus cluster (min_agree_sources only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub consensus_sources: Vec<String>,
+
+    /// Sources left outside the consensus cluster
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded_sources: Vec<String>,
+
+    /// Hex Merkle root over the contributing (source, value, timestamp) leaves (merkle_contributions only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contributions_merkle_root: Option<String>,
+
+    /// Contributing sources with a verified signature (only when a source has verify_signature)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified_sources: Option<usize>,
+
+    /// Sources removed by a filter stage (gates, precision, consensus, outliers) and why
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<DroppedSource>,
+
+    /// Usable but weaker than a normal result: aggregated from a partial set after the deadline
+    /// (aggregate_partial), backed by a single source (single_source_ok: false), clamped to
+    /// reference_price, an ambiguous median, snapshot skew or a monotonic regression with the degrade
+    /// action, or any degraded output
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub degraded: bool,
+
+    /// Per-output results of a multi-output request (`value` mirrors the first successful one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<Vec<NamedValue>>,
+}
+
+/// Result of one named output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedValue {
+    /// Output name from the request
+    pub name: String,
+
+    /// Aggregated value (None if this output failed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<DataValue>,
+
+    /// Sources that contributed to this output
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+
+    /// Error/warning for this output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// A source that responded but was left out of the aggregate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroppedSource {
+    pub name: String,
+    pub reason: String,
+}
+
+impl DroppedSource {
+    pub fn new(name: &str, reason: String) -> Self {
+        DroppedSource { name: name.to_string(), reason }
+    }
+
+    /// "a (reason), b (reason)" for messages
+    pub fn summary(dropped: &[DroppedSource]) -> String {
+        dropped.iter()
+            .map(|d| format!("{} ({})", d.name, d.reason))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Diagnostics for a single source that contributed to the result
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceDetail {
+    /// Source name as reported in `sources`
+    pub name: String,
+
+    /// Value reported by this source (always_include_sources)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<DataValue>,
+
+    /// Distance of a canary's value from the aggregate in percent (canary sources only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary_deviation_percent: Option<f64>,
+
+    /// Index of the staleness tier this source fell into (staleness_tiers only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<usize>,
+
+    /// Final weight of this source in a weighted aggregate (robust_irls)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+
+    /// Timestamp reported by this source (the aggregate uses the newest one)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+
+    /// Total size of HTTP response bodies in bytes (perf_detail)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_bytes: Option<usize>,
+
+    /// Total time spent parsing response bodies in microseconds (perf_detail)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_micros: Option<u64>,
+
+    /// Quote currency when the source doesn't quote in USD (e.g. "KRW")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+
+    /// Served from the cache instead of a live fetch
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cached: bool,
+
+    /// Mirror host that served the data after the primary host failed to connect
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+
+    /// Raw response body, redacted and truncated (capture_raw_for only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_response: Option<String>,
+
+    /// Allowed key that signed the value (verify_signature only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed_by: Option<String>,
+
+    /// Final request URLs with secrets redacted, in call order (debug_urls)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+
+    /// Rate-limit headers of the last response, e.g. x-ratelimit-remaining (debug_urls)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rate_limit: BTreeMap<String, String>,
 }
 
 /// Response for a single data request
@@ -153,6 +1243,67 @@ pub struct DataResponse {
 
     /// Error/info message (None if successful)
     pub message: Option<String>,
+
+    /// Stored samples, oldest first (history mode only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<HistorySample>>,
+
+    /// Per-source latency (probe mode only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe: Option<Vec<ProbeStats>>,
+}
+
+impl DataResponse {
+    /// Failed response with an error message and no data
+    pub fn failed(id: &str, message: String) -> Self {
+        DataResponse {
+            id: id.to_string(),
+            data: None,
+            message: Some(message),
+            history: None,
+            probe: None,
+        }
+    }
+
+    /// Append a note to the message ("existing. note")
+    pub fn append_message(&mut self, note: String) {
+        self.message = Some(match self.message.take() {
+            Some(msg) => format!("{}. {}", msg, note),
+            None => note,
+        });
+    }
+}
+
+/// Latency distribution of one source over the probe iterations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeStats {
+    /// Source name (label if set)
+    pub source: String,
+
+    /// Successful calls
+    pub ok: usize,
+
+    /// Failed calls
+    pub failed: usize,
+
+    /// Latency of successful calls in milliseconds (None if every call failed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avg_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_ms: Option<u64>,
+
+    /// Last error seen, if any call failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Aggregated value persisted in the state file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySample {
+    pub timestamp: u64,
+    pub value: f64,
 }
 
 /// Main response structure
@@ -160,12 +1311,179 @@ pub struct DataResponse {
 pub struct OracleResponse {
     /// List of data responses
     pub results: Vec<DataResponse>,
+
+    /// Settings applied after defaults (only when `echo_effective_config` is enabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_config: Option<EffectiveConfig>,
+
+    /// Hex SHA-256 of the raw stdin bytes (only when `echo_request` is enabled)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_hash: Option<String>,
+    /// Hex ed25519 signature over this response without the two signature fields (ORACLE_ED25519_KEY)
+    /// Must stay the last fields so the signed bytes are a prefix of the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ed25519_signature: Option<String>,
+
+    /// Signer public key in NEAR format ("ed25519:<base58>")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ed25519_pubkey: Option<String>,
+}
+
+/// Execution settings as applied, with every default filled in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    pub max_retries: u32,
+    pub retry_backoff_ms: u64,
+    pub deviation_epsilon: f64,
+    pub follow_redirects: bool,
+    pub timeout_behavior: TimeoutBehavior,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_deadline_secs: Option<u64>,
+    /// Only reported when a cache is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl_secs: Option<u64>,
+    pub requests: Vec<EffectiveRequest>,
+}
+
+/// Per-request settings as applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EffectiveRequest {
+    pub id: String,
+    pub aggregation_method: AggregationMethod,
+    pub min_sources_num: usize,
+    pub max_deviation_percent: f64,
+    pub on_deviation: OnDeviation,
+    pub check_deviation: bool,
+}
+
+impl EffectiveConfig {
+    pub fn new(request: &OracleRequest) -> Self {
+        let config = &request.config;
+        EffectiveConfig {
+            max_retries: config.max_retries,
+            retry_backoff_ms: config.retry_backoff_ms.unwrap_or(DEFAULT_RETRY_BACKOFF_MS),
+            deviation_epsilon: config.deviation_epsilon.unwrap_or(DEFAULT_DEVIATION_EPSILON),
+            follow_redirects: config.follow_redirects.unwrap_or(true),
+            timeout_behavior: config.timeout_behavior.clone(),
+            total_deadline_secs: config.total_deadline_secs,
+            cache_ttl_secs: config.cache_path.as_ref().map(|_| config.cache_ttl_secs.unwrap_or(DEFAULT_CACHE_TTL_SECS)),
+            requests: request.requests.iter()
+                .map(|data_req| EffectiveRequest {
+                    id: data_req.id.clone(),
+                    aggregation_method: data_req.aggregation_method.clone(),
+                    min_sources_num: data_req.min_sources_num,
+                    max_deviation_percent: data_req.max_deviation(request.max_price_deviation_percent),
+                    on_deviation: data_req.on_deviation.clone(),
+                    check_deviation: data_req.check_deviation,
+                })
+                .collect(),
+        }
+    }
 }
 
 /// Internal structure for source data result
 #[derive(Debug, Clone)]
 pub struct SourcePrice {
     pub source_name: String,
+    /// Built-in source that produced the value (e.g. "binance"); unlike source_name, never a label
+    pub provider: String,
     pub value: DataValue,
     pub timestamp: u64,
+    /// Uncertainty of the value in the same units (e.g. Pyth `conf`), if the source reports one
+    pub confidence: Option<f64>,
+    /// Quote currency if not USD (e.g. "KRW" for Bithumb)
+    pub currency: Option<String>,
+    /// Explicit weight from the source config (reputation_weighted)
+    pub weight: Option<f64>,
+    /// Staleness tier weight, multiplied into every weighted method (default: 1)
+    pub tier_weight: Option<f64>,
+    pub detail: SourceDetail,
+}
+
+impl SourcePrice {
+    pub fn new(source_name: String, value: DataValue, timestamp: u64) -> Self {
+        SourcePrice {
+            provider: source_name.clone(),
+            source_name,
+            value,
+            timestamp,
+            confidence: None,
+            currency: None,
+            weight: None,
+            tier_weight: None,
+            detail: SourceDetail::default(),
+        }
+    }
+
+    /// Rescale a numeric value (and its confidence interval) to another unit
+    pub fn convert_units(&mut self, factor: f64) {
+        if let DataValue::Number(value) = self.value {
+            self.value = DataValue::Number(value * factor);
+            self.confidence = self.confidence.map(|c| c * factor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn oracle_request(value: serde_json::Value) -> OracleRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn per_request_deviation_overrides_the_global_limit() {
+        let request = oracle_request(json!({
+            "max_price_deviation_percent": 5.0,
+            "requests": [
+                {"id": "usd-coin", "sources": [{"name": "coingecko"}], "max_deviation_percent": 0.5},
+                {"id": "pepe", "sources": [{"name": "coingecko"}], "max_deviation_percent": 15.0},
+                {"id": "bitcoin", "sources": [{"name": "coingecko"}]}
+            ]
+        }));
+
+        let limits: Vec<f64> = request.requests.iter()
+            .map(|data_req| data_req.max_deviation(request.max_price_deviation_percent))
+            .collect();
+        assert_eq!(limits, vec![0.5, 15.0, 5.0]);
+
+        let effective = EffectiveConfig::new(&request);
+        let echoed: Vec<f64> = effective.requests.iter().map(|r| r.max_deviation_percent).collect();
+        assert_eq!(echoed, limits);
+    }
+
+    #[test]
+    fn troy_ounce_price_converts_to_grams() {
+        let mut price = SourcePrice::new("twelvedata".to_string(), DataValue::Number(3110.34768), 1_700_000_000);
+        price.confidence = Some(31.1034768);
+
+        price.convert_units(1.0 / 31.1034768);
+
+        let per_gram = price.value.as_number().unwrap();
+        assert!((per_gram - 100.0).abs() < 1e-9, "{}", per_gram);
+        assert!((price.confidence.unwrap() - 1.0).abs() < 1e-12);
+
+        // Non-numeric values are left alone
+        let mut text = SourcePrice::new("custom".to_string(), DataValue::Text("gold".to_string()), 1_700_000_000);
+        text.convert_units(1.0 / 31.1034768);
+        assert!(matches!(&text.value, DataValue::Text(t) if t == "gold"));
+    }
+
+    #[test]
+    fn numeric_strings_parse_as_numbers() {
+        let value = DataValue::from_json(&json!("123"), &ValueType::Number).unwrap();
+        assert!(matches!(value, DataValue::Number(n) if n == 123.0));
+
+        let value = DataValue::from_json(&json!(" 0.5 "), &ValueType::Number).unwrap();
+        assert_eq!(value.as_number(), Some(0.5));
+
+        assert!(DataValue::from_json(&json!("abc"), &ValueType::Number).is_err());
+        for text in ["NaN", "inf", "-infinity", "1e400"] {
+            let err = DataValue::from_json(&json!(text), &ValueType::Number).unwrap_err();
+            assert!(err.contains("not a finite number"), "{}", err);
+        }
+        assert!(DataValue::from_json(&json!(true), &ValueType::Number).is_err());
+    }
 }
@@ -0,0 +1,463 @@
+use crate::aggregation;
+use crate::sources;
+use crate::types::{
+    AggregationMethod, CustomSourceConfig, DataRequest, DataValue, DiscoveryConfig, ExecutionConfig, SeriesMethod, SeriesReduce, SourceGates, SourcePrice, ValueType,
+    DEFAULT_MAX_CUSTOM_HEADERS, DEFAULT_MAX_HEADER_BYTES,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Reject requests that can never succeed before any HTTP call is made
+pub fn validate_request(data_req: &DataRequest, config: &ExecutionConfig) -> Result<(), String> {
+    if !data_req.outputs.is_empty() {
+        return validate_outputs(data_req, config);
+    }
+
+    // The source list is only known after the directory call
+    if let Some(discovery) = &data_req.discovery {
+        return validate_discovery(data_req, discovery, config);
+    }
+
+    // Distinguish a misconfigured request from an outage where every source failed
+    if data_req.sources.is_empty() {
+        return Err("Invalid request: no sources configured".to_string());
+    }
+
+    // Canaries never contribute, so only the other sources count toward min_sources_num
+    let contributing = data_req.sources.iter().filter(|s| !s.canary).count();
+    if contributing == 0 {
+        return Err("Invalid request: every source is a canary".to_string());
+    }
+    if contributing < data_req.sources.len()
+        && (data_req.aggregation_method.is_boolean() || matches!(data_req.aggregation_method, AggregationMethod::MaxMinusMin))
+    {
+        return Err("Invalid request: canary sources need a numeric price aggregation (not boolean or max_minus_min)".to_string());
+    }
+
+    // min_sources_num larger than the source list is a guaranteed failure
+    if data_req.min_sources_num > contributing {
+        return Err(format!(
+            "Invalid request: requires {} sources but only {} configured",
+            data_req.min_sources_num,
+            contributing
+        ));
+    }
+
+    // Oversized or malformed custom headers are rejected before any request is built
+    for source in &data_req.sources {
+        // A fixed-currency API would otherwise have its value relabeled, not converted
+        if let (Some(quote), Some(fixed)) = (&source.quote, sources::fixed_quote(config.resolve_source_name(&source.name))) {
+            if !quote.eq_ignore_ascii_case(fixed) {
+                return Err(format!(
+                    "Invalid request: source '{}' always quotes in {}, so quote '{}' can't be applied",
+                    source.name, fixed, quote
+                ));
+            }
+        }
+
+        // Only custom sources return arbitrary JSON that can carry a signature
+        if let Some(verify) = &source.verify_signature {
+            if source.custom.is_none() {
+                return Err(format!("Invalid request: source '{}': verify_signature requires a custom source", source.name));
+            }
+            if verify.allowed_pubkeys.is_empty() {
+                return Err(format!("Invalid request: source '{}': verify_signature has no allowed_pubkeys", source.name));
+            }
+            // The signature covers one response's json_path, not values read across pages, levels or a series
+            if let Some(custom) = &source.custom {
+                let unsigned = [
+                    ("paginate", custom.paginate.is_some()),
+                    ("depth", custom.depth.is_some()),
+                    ("series_reduce", custom.series_reduce.is_some()),
+                ];
+                if let Some((field, _)) = unsigned.iter().find(|(_, set)| *set) {
+                    return Err(format!("Invalid request: source '{}': verify_signature can't be combined with {}", source.name, field));
+                }
+            }
+        }
+
+        if let Some(custom) = &source.custom {
+            validate_headers(custom, config)
+                .map_err(|e| format!("Invalid request: source '{}': {}", source.name, e))?;
+
+            if let Some(series) = &custom.series_reduce {
+                validate_series_reduce(custom, series)
+                    .map_err(|e| format!("Invalid request: source '{}': {}", source.name, e))?;
+            }
+
+            // Stripping only applies to the json_path value, and only when it is read as a number
+            if custom.strip_chars.is_some()
+                && (custom.depth.is_some() || custom.paginate.is_some() || custom.series_reduce.is_some()
+                    || !matches!(custom.value_type, ValueType::Number | ValueType::Integer))
+            {
+                return Err(format!(
+                    "Invalid request: source '{}': strip_chars requires a number or integer json_path value (not depth, paginate or series_reduce)",
+                    source.name
+                ));
+            }
+        }
+    }
+
+    // The fallback replaces a numeric median, so it must be another numeric average
+    if let Some(fallback) = &data_req.median_ambiguity_fallback {
+        if fallback.is_boolean() || matches!(fallback, AggregationMethod::Median | AggregationMethod::MaxMinusMin) {
+            return Err("Invalid request: median_ambiguity_fallback must be a numeric method other than median or max_minus_min".to_string());
+        }
+        if data_req.use_decimal && !aggregation::supports_decimal(fallback) {
+            return Err("Invalid request: median_ambiguity_fallback must be average with use_decimal".to_string());
+        }
+    }
+
+    // Weighted methods need float weights, so they have no fixed-point path
+    if data_req.use_decimal && !aggregation::supports_decimal(&data_req.aggregation_method) {
+        return Err(format!(
+            "Invalid request: use_decimal supports average, median and max_minus_min, not {}",
+            serde_json::to_string(&data_req.aggregation_method).unwrap_or_default().trim_matches('"')
+        ));
+    }
+
+    // Tiers are matched in order, so they must be ascending; weights scale the aggregate
+    if !data_req.staleness_tiers.is_empty() {
+        let ascending = data_req.staleness_tiers.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        if !ascending {
+            return Err("Invalid request: staleness_tiers must be in ascending max_age_secs order".to_string());
+        }
+        if let Some((_, weight)) = data_req.staleness_tiers.iter().find(|(_, w)| !w.is_finite() || *w < 0.0) {
+            return Err(format!("Invalid request: staleness tier weight must be >= 0, got {}", weight));
+        }
+        if data_req.use_decimal {
+            return Err("Invalid request: staleness_tiers can't be combined with use_decimal".to_string());
+        }
+    }
+
+    // A conversion can only use a rate that is resolved up front
+    if let Some(rate_id) = &data_req.convert_rate {
+        if !config.shared_rates.iter().any(|rate| &rate.id == rate_id) {
+            return Err(format!("Invalid request: convert_rate '{}' is not in config.shared_rates", rate_id));
+        }
+    }
+
+    // The last value to compare against lives in the state file
+    if data_req.monotonic && config.state_path.is_none() {
+        return Err("Invalid request: monotonic requires config.state_path".to_string());
+    }
+
+    // The clamp needs a reference and a non-negative band
+    if let Some(max_change) = data_req.max_change_percent_from_reference {
+        if data_req.reference_price.is_none() {
+            return Err("Invalid request: max_change_percent_from_reference requires reference_price".to_string());
+        }
+        if max_change.is_nan() || max_change < 0.0 {
+            return Err(format!("Invalid request: max_change_percent_from_reference must be >= 0, got {}", max_change));
+        }
+    }
+
+    Ok(())
+}
+
+/// A series is a `*` path over a single response, reduced to one number
+fn validate_series_reduce(custom: &CustomSourceConfig, series: &SeriesReduce) -> Result<(), String> {
+    if custom.depth.is_some() || custom.paginate.is_some() {
+        return Err("series_reduce can't be combined with depth or paginate".to_string());
+    }
+    if !custom.json_path.split('.').any(|part| part == "*") {
+        return Err(format!("series_reduce needs a '*' in json_path (e.g. \"trades.*.price\"), got '{}'", custom.json_path));
+    }
+    if !matches!(custom.value_type, ValueType::Number) {
+        return Err("series_reduce requires value_type number".to_string());
+    }
+    if matches!(series.method, SeriesMethod::TimeWeighted) && series.time_path.is_none() {
+        return Err("time_weighted series_reduce requires time_path".to_string());
+    }
+    Ok(())
+}
+
+/// Header count, per-header size, duplicate names (case-insensitive) and CR/LF injection
+fn validate_headers(custom: &CustomSourceConfig, config: &ExecutionConfig) -> Result<(), String> {
+    let max_headers = config.max_custom_headers.unwrap_or(DEFAULT_MAX_CUSTOM_HEADERS);
+    let max_bytes = config.max_header_bytes.unwrap_or(DEFAULT_MAX_HEADER_BYTES);
+
+    if custom.headers.len() > max_headers {
+        return Err(format!("{} headers (max {})", custom.headers.len(), max_headers));
+    }
+
+    let mut seen: Vec<String> = Vec::new();
+    for (name, value) in &custom.headers {
+        if name.is_empty() {
+            return Err("empty header name".to_string());
+        }
+        if name.len() + value.len() > max_bytes {
+            return Err(format!("header '{}' is {} bytes (max {})", name, name.len() + value.len(), max_bytes));
+        }
+        if name.contains(['\r', '\n', ':']) || value.contains(['\r', '\n']) {
+            return Err(format!("header '{}' contains a line break (or ':' in the name)", name.trim()));
+        }
+
+        let lower = name.to_ascii_lowercase();
+        if seen.contains(&lower) {
+            return Err(format!("duplicate header '{}'", name));
+        }
+        seen.push(lower);
+    }
+
+    Ok(())
+}
+
+/// Static checks for a discovery request; the source count is checked once the list is known
+fn validate_discovery(data_req: &DataRequest, discovery: &DiscoveryConfig, config: &ExecutionConfig) -> Result<(), String> {
+    if discovery.max_sources == Some(0) {
+        return Err("Invalid request: discovery.max_sources must be at least 1".to_string());
+    }
+
+    validate_headers(&discovery.directory, config)
+        .map_err(|e| format!("Invalid request: discovery directory: {}", e))?;
+
+    // Checked as a one-source request so the template gets the same checks as a static source
+    let template_req = DataRequest {
+        sources: vec![discovery.template.clone()],
+        min_sources_num: 1,
+        discovery: None,
+        ..data_req.clone()
+    };
+    validate_request(&template_req, config)
+}
+
+/// Named outputs replace `sources`; each output must be valid on its own
+fn validate_outputs(data_req: &DataRequest, config: &ExecutionConfig) -> Result<(), String> {
+    if !data_req.sources.is_empty() {
+        return Err("Invalid request: use either sources or outputs, not both".to_string());
+    }
+
+    if data_req.discovery.is_some() {
+        return Err("Invalid request: discovery can't be combined with outputs".to_string());
+    }
+
+    // The depeg check reports its own outputs
+    if data_req.depeg_threshold_percent.is_some() {
+        return Err("Invalid request: depeg_threshold_percent can't be combined with outputs".to_string());
+    }
+
+    let mut names: Vec<&str> = Vec::new();
+    for output in &data_req.outputs {
+        if names.contains(&output.name.as_str()) {
+            return Err(format!("Invalid request: duplicate output '{}'", output.name));
+        }
+        names.push(&output.name);
+
+        validate_request(&data_req.output_request(output), config)
+            .map_err(|e| format!("{} (output '{}')", e, output.name))?;
+    }
+
+    Ok(())
+}
+
+/// For each request, its 1-based occurrence number if its id is shared with another request
+pub fn duplicate_id_occurrences(requests: &[DataRequest]) -> Vec<Option<usize>> {
+    let mut totals: HashMap<&str, usize> = HashMap::new();
+    for data_req in requests {
+        *totals.entry(data_req.id.as_str()).or_default() += 1;
+    }
+
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    requests.iter()
+        .map(|data_req| {
+            let occurrence = seen.entry(data_req.id.as_str()).or_default();
+            *occurrence += 1;
+            if totals[data_req.id.as_str()] > 1 { Some(*occurrence) } else { None }
+        })
+        .collect()
+}
+
+/// Why a fetched source value was not allowed to contribute
+#[derive(Debug, Clone, PartialEq)]
+pub enum DropReason {
+    Stale { age_secs: u64, max_age_secs: u64 },
+    BelowMin { value: f64, min: f64 },
+    AboveMax { value: f64, max: f64 },
+    BeyondTiers { age_secs: u64, max_age_secs: u64 },
+    WrongType { expected: String, got: String },
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DropReason::Stale { age_secs, max_age_secs } => {
+                write!(f, "stale: {}s old, max {}s", age_secs, max_age_secs)
+            }
+            DropReason::BelowMin { value, min } => write!(f, "{} below min {}", value, min),
+            DropReason::AboveMax { value, max } => write!(f, "{} above max {}", value, max),
+            DropReason::BeyondTiers { age_secs, max_age_secs } => {
+                write!(f, "stale: {}s old, beyond the last tier ({}s)", age_secs, max_age_secs)
+            }
+            DropReason::WrongType { expected, got } => write!(f, "expected {}, got {}", expected, got),
+        }
+    }
+}
+
+/// Single acceptance gate for a source value: it must be fresh AND within bounds
+/// Bounds only apply to numbers; text and boolean values pass them unchanged
+pub fn accept_source(price: &SourcePrice, gates: &SourceGates, now: u64) -> Result<(), DropReason> {
+    if let Some(max_age_secs) = gates.max_age_secs {
+        let age_secs = now.saturating_sub(price.timestamp);
+        if age_secs > max_age_secs {
+            return Err(DropReason::Stale { age_secs, max_age_secs });
+        }
+    }
+
+    if let DataValue::Number(value) = price.value {
+        if let Some(min) = gates.min_value.filter(|&min| value < min) {
+            return Err(DropReason::BelowMin { value, min });
+        }
+        if let Some(max) = gates.max_value.filter(|&max| value > max) {
+            return Err(DropReason::AboveMax { value, max });
+        }
+    }
+
+    Ok(())
+}
+
+/// Staleness tier of a source: (index, weight) of the first tier its age fits in
+pub fn staleness_tier(price: &SourcePrice, tiers: &[(u64, f64)], now: u64) -> Result<(usize, f64), DropReason> {
+    let age_secs = now.saturating_sub(price.timestamp);
+    tiers.iter()
+        .position(|(max_age_secs, _)| age_secs <= *max_age_secs)
+        .map(|index| (index, tiers[index].1))
+        .ok_or(DropReason::BeyondTiers {
+            age_secs,
+            max_age_secs: tiers.last().map(|(max_age_secs, _)| *max_age_secs).unwrap_or(0),
+        })
+}
+
+/// A source with a declared `value_type` must return that type; mixed types never reach aggregation
+pub fn check_value_type(price: &SourcePrice, expected: Option<&ValueType>) -> Result<(), DropReason> {
+    match expected {
+        Some(value_type) if !price.value.is_type(value_type) => {
+            let expected = match value_type {
+                ValueType::Number => "a number",
+                ValueType::Integer => "an integer",
+                ValueType::String => "a string",
+                ValueType::Boolean => "a boolean",
+            };
+            Err(DropReason::WrongType {
+                expected: expected.to_string(),
+                got: serde_json::to_string(&price.value).unwrap_or_default(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// NaN/Infinity would serialize as null, so a non-finite aggregate is an error, not a value
+pub fn check_finite(value: &DataValue) -> Result<(), String> {
+    match value.as_number() {
+        Some(number) if !number.is_finite() => Err(format!("Aggregation failed: non-finite aggregate ({:?})", value)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data_request(value: serde_json::Value) -> DataRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn execution_config() -> ExecutionConfig {
+        serde_json::from_value(json!({})).unwrap()
+    }
+
+    #[test]
+    fn empty_source_list_is_rejected_up_front() {
+        let data_req = data_request(json!({"id": "bitcoin", "sources": []}));
+        assert_eq!(
+            validate_request(&data_req, &execution_config()),
+            Err("Invalid request: no sources configured".to_string())
+        );
+
+        let data_req = data_request(json!({"id": "bitcoin", "sources": [{"name": "coingecko"}]}));
+        assert_eq!(validate_request(&data_req, &execution_config()), Ok(()));
+    }
+
+    #[test]
+    fn shared_ids_are_numbered_by_occurrence() {
+        let requests = vec![
+            data_request(json!({"id": "bitcoin", "sources": [{"name": "coingecko"}]})),
+            data_request(json!({"id": "near", "sources": [{"name": "coingecko"}]})),
+            data_request(json!({"id": "bitcoin", "sources": [{"name": "binance", "id": "BTCUSDT"}]})),
+        ];
+
+        assert_eq!(duplicate_id_occurrences(&requests), vec![Some(1), None, Some(2)]);
+    }
+
+    #[test]
+    fn overflowing_average_is_rejected_as_non_finite() {
+        let prices = vec![
+            SourcePrice::new("a".to_string(), DataValue::Number(f64::MAX), 0),
+            SourcePrice::new("b".to_string(), DataValue::Number(f64::MAX), 0),
+        ];
+        let aggregate = aggregation::aggregate_prices(&prices, &AggregationMethod::Average, false, false).unwrap();
+        assert!(aggregate.is_infinite());
+
+        let error = check_finite(&DataValue::Number(aggregate)).unwrap_err();
+        assert!(error.starts_with("Aggregation failed: non-finite aggregate"), "{}", error);
+        assert!(check_finite(&DataValue::Number(f64::NAN)).is_err());
+        assert!(check_finite(&DataValue::Number(1.5)).is_ok());
+        assert!(check_finite(&DataValue::Text("n/a".to_string())).is_ok());
+    }
+
+    #[test]
+    fn quote_must_match_a_fixed_currency_source() {
+        let request = |name: &str, quote: &str| data_request(json!({
+            "id": "btc",
+            "sources": [{"name": name, "id": "btc", "quote": quote}]
+        }));
+        let config = execution_config();
+
+        assert!(validate_request(&request("messari", "USD"), &config).is_ok());
+        let err = validate_request(&request("messari", "EUR"), &config).unwrap_err();
+        assert!(err.contains("always quotes in USD"), "{}", err);
+        assert!(validate_request(&request("bithumb", "USD"), &config).is_err());
+        assert!(validate_request(&request("coingecko", "eur"), &config).is_ok());
+    }
+
+    #[test]
+    fn signed_sources_reject_multi_value_extraction() {
+        let request = |custom: serde_json::Value| data_request(json!({
+            "id": "eth",
+            "sources": [{
+                "name": "custom",
+                "custom": custom,
+                "verify_signature": {"signature_path": "sig", "pubkey_path": "signer", "allowed_pubkeys": ["00"]}
+            }]
+        }));
+        let config = execution_config();
+
+        let plain = json!({"url": "https://feed.example.com/eth", "json_path": "payload.price"});
+        assert!(validate_request(&request(plain), &config).is_ok());
+
+        let series = json!({
+            "url": "https://feed.example.com/eth",
+            "json_path": "trades.*.price",
+            "series_reduce": {"method": "average"}
+        });
+        let err = validate_request(&request(series), &config).unwrap_err();
+        assert!(err.contains("verify_signature can't be combined with series_reduce"), "{}", err);
+    }
+
+    #[test]
+    fn decimal_rejects_weighted_methods() {
+        let request = |method: &str| data_request(json!({
+            "id": "btc",
+            "sources": [{"name": "coingecko"}, {"name": "binance"}],
+            "aggregation_method": method,
+            "use_decimal": true
+        }));
+        let config = execution_config();
+
+        assert!(validate_request(&request("average"), &config).is_ok());
+        let err = validate_request(&request("weighted_avg"), &config).unwrap_err();
+        assert!(err.contains("not weighted_avg"), "{}", err);
+    }
+}